@@ -0,0 +1,34 @@
+-- Element types are now a fixed set (see src/elements.rs)
+ALTER TABLE form_elements ADD COLUMN legacy_element_type VARCHAR(50);
+
+-- Map the free-form names the frontend used to send onto the new kinds
+UPDATE form_elements
+SET element_type = CASE lower(trim(element_type))
+    WHEN 'text' THEN 'short_text'
+    WHEN 'short_answer' THEN 'short_text'
+    WHEN 'textarea' THEN 'long_text'
+    WHEN 'paragraph' THEN 'long_text'
+    WHEN 'radio' THEN 'single_choice'
+    WHEN 'checkbox' THEN 'multiple_choice'
+    WHEN 'checkboxes' THEN 'multiple_choice'
+    WHEN 'select' THEN 'dropdown'
+    WHEN 'scale' THEN 'linear_scale'
+    WHEN 'stars' THEN 'rating'
+    WHEN 'file' THEN 'file_upload'
+    ELSE lower(trim(element_type))
+END;
+
+-- Anything still unknown is flagged: the original type is kept in
+-- legacy_element_type and the row falls back to short_text until edited
+UPDATE form_elements
+SET legacy_element_type = element_type,
+    element_type = 'short_text'
+WHERE element_type NOT IN (
+    'short_text', 'long_text', 'email', 'number', 'single_choice', 'multiple_choice',
+    'dropdown', 'date', 'time', 'rating', 'linear_scale', 'file_upload'
+);
+
+ALTER TABLE form_elements ADD CONSTRAINT form_elements_element_type_check CHECK (element_type IN (
+    'short_text', 'long_text', 'email', 'number', 'single_choice', 'multiple_choice',
+    'dropdown', 'date', 'time', 'rating', 'linear_scale', 'file_upload'
+));
@@ -0,0 +1,2 @@
+-- Show/hide and skip-to rules, see src/logic.rs
+ALTER TABLE form_elements ADD COLUMN logic JSONB;
@@ -0,0 +1,19 @@
+-- Sections (pages) of a form
+CREATE TABLE form_sections (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    order_index INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_form_sections_form_id ON form_sections(form_id);
+
+-- Elements without a section are shown before the first section; their
+-- order_index is relative to the section they belong to
+ALTER TABLE form_elements ADD COLUMN section_id UUID REFERENCES form_sections(id) ON DELETE SET NULL;
+CREATE INDEX idx_form_elements_section_id ON form_elements(section_id);
+
+ALTER TABLE form_responses ADD COLUMN pages_reached UUID[];
@@ -0,0 +1,26 @@
+-- Published snapshots of a form and its elements
+CREATE TABLE form_versions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    version_number INTEGER NOT NULL,
+    snapshot JSONB NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (form_id, version_number)
+);
+
+CREATE INDEX idx_form_versions_form_id ON form_versions(form_id);
+
+-- Versions are immutable once published
+CREATE FUNCTION prevent_form_version_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'form versions are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER form_versions_immutable
+    BEFORE UPDATE ON form_versions
+    FOR EACH ROW EXECUTE FUNCTION prevent_form_version_update();
+
+ALTER TABLE form_responses ADD COLUMN version_id UUID REFERENCES form_versions(id);
+CREATE INDEX idx_form_responses_version_id ON form_responses(version_id);
@@ -0,0 +1,8 @@
+-- Existing forms were already accepting responses, so they start out published
+ALTER TABLE forms ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'published'
+    CHECK (status IN ('draft', 'published', 'closed', 'archived'));
+ALTER TABLE forms ALTER COLUMN status SET DEFAULT 'draft';
+
+ALTER TABLE forms ADD COLUMN opens_at TIMESTAMPTZ;
+ALTER TABLE forms ADD COLUMN closes_at TIMESTAMPTZ;
+ALTER TABLE forms ADD COLUMN closed_message TEXT;
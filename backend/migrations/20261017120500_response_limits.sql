@@ -0,0 +1,9 @@
+ALTER TABLE forms ADD COLUMN max_responses INTEGER CHECK (max_responses > 0);
+ALTER TABLE forms ADD COLUMN max_responses_per_respondent INTEGER CHECK (max_responses_per_respondent > 0);
+ALTER TABLE forms ADD COLUMN max_responses_per_client INTEGER CHECK (max_responses_per_client > 0);
+
+-- Hash identifying the device an anonymous response came from
+ALTER TABLE form_responses ADD COLUMN client_key VARCHAR(64);
+
+CREATE INDEX idx_form_responses_respondent ON form_responses(form_id, respondent_id);
+CREATE INDEX idx_form_responses_client_key ON form_responses(form_id, client_key);
@@ -0,0 +1,10 @@
+ALTER TABLE forms ADD COLUMN is_quiz BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE forms ADD COLUMN show_score_immediately BOOLEAN NOT NULL DEFAULT false;
+
+-- Elements without a correct answer are not graded
+ALTER TABLE form_elements ADD COLUMN correct_answer JSONB;
+ALTER TABLE form_elements ADD COLUMN points INTEGER NOT NULL DEFAULT 1 CHECK (points >= 0);
+
+ALTER TABLE form_responses ADD COLUMN score INTEGER;
+ALTER TABLE form_responses ADD COLUMN max_score INTEGER;
+ALTER TABLE form_responses ADD COLUMN grading JSONB;
@@ -0,0 +1,20 @@
+-- share_type used to be free text; anything unrecognised becomes an open link
+UPDATE form_shares SET share_type = 'link' WHERE share_type NOT IN ('link', 'password', 'invite');
+ALTER TABLE form_shares ADD CONSTRAINT form_shares_share_type_check
+    CHECK (share_type IN ('link', 'password', 'invite'));
+
+ALTER TABLE form_shares ADD COLUMN password_hash VARCHAR(255);
+
+-- One row per recipient of an invite-only share; each token works once
+CREATE TABLE share_invites (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    share_id UUID NOT NULL REFERENCES form_shares(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    token VARCHAR(255) NOT NULL UNIQUE,
+    response_id UUID REFERENCES form_responses(id) ON DELETE SET NULL,
+    responded_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (share_id, email)
+);
+
+CREATE INDEX idx_share_invites_share_id ON share_invites(share_id);
@@ -0,0 +1,17 @@
+-- element_id deliberately has no foreign key: uploads must survive the element
+-- being edited away or a version rollback re-creating it
+CREATE TABLE file_uploads (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    element_id UUID NOT NULL,
+    response_id UUID REFERENCES form_responses(id) ON DELETE CASCADE,
+    uploaded_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    storage_key VARCHAR(512) NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_file_uploads_form_id ON file_uploads(form_id);
+CREATE INDEX idx_file_uploads_response_id ON file_uploads(response_id);
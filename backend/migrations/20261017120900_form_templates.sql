@@ -0,0 +1,58 @@
+-- user_id is NULL for the built-in templates everyone can use
+CREATE TABLE form_templates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    category VARCHAR(100),
+    definition JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_form_templates_user_id ON form_templates(user_id);
+
+INSERT INTO form_templates (id, user_id, name, description, category, definition) VALUES
+(
+    '8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e0001', NULL, 'Contact form',
+    'Collect names, email addresses and messages.', 'general',
+    '{
+        "title": "Contact us",
+        "description": "We usually reply within two working days.",
+        "settings": {"allow_anonymous": true},
+        "elements": [
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e1001", "element_type": "short_text", "question": "Name", "required": true, "order_index": 0},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e1002", "element_type": "email", "question": "Email address", "required": true, "order_index": 1},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e1003", "element_type": "long_text", "question": "Message", "required": true, "order_index": 2, "options": {"max_length": 2000}}
+        ]
+    }'
+),
+(
+    '8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e0002', NULL, 'Event registration',
+    'Register attendees and their session choices.', 'events',
+    '{
+        "title": "Event registration",
+        "settings": {"allow_anonymous": true, "max_responses_per_client": 1},
+        "elements": [
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2001", "element_type": "short_text", "question": "Full name", "required": true, "order_index": 0},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2002", "element_type": "email", "question": "Email address", "required": true, "order_index": 1},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2003", "element_type": "multiple_choice", "question": "Which sessions will you attend?", "required": true, "order_index": 2, "options": {"choices": ["Morning", "Afternoon", "Evening"], "min_selections": 1}},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2004", "element_type": "single_choice", "question": "Do you have dietary requirements?", "required": true, "order_index": 3, "options": {"choices": ["No", "Yes"]}},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2005", "element_type": "short_text", "question": "Please describe them", "required": true, "order_index": 4,
+             "logic": {"show_if": {"answer": {"element_id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e2004", "operator": "equals", "value": "Yes"}}}}
+        ]
+    }'
+),
+(
+    '8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e0003', NULL, 'Customer feedback',
+    'Measure satisfaction and collect suggestions.', 'feedback',
+    '{
+        "title": "How did we do?",
+        "settings": {"allow_anonymous": true},
+        "elements": [
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e3001", "element_type": "rating", "question": "How satisfied are you overall?", "required": true, "order_index": 0, "options": {"max": 5}},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e3002", "element_type": "linear_scale", "question": "How likely are you to recommend us to a friend?", "required": true, "order_index": 1, "options": {"min": 0, "max": 10, "min_label": "Not likely", "max_label": "Very likely"}},
+            {"id": "8a6c1c2e-5b0a-4b8e-9a57-2f0d8b1e3003", "element_type": "long_text", "question": "What could we do better?", "required": false, "order_index": 2}
+        ]
+    }'
+);
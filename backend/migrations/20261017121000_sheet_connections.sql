@@ -0,0 +1,22 @@
+-- A form's linked Google Sheet. Responses are appended to it in the order
+-- they arrived; the sheet's columns never move once written.
+CREATE TABLE sheet_connections (
+    form_id UUID PRIMARY KEY REFERENCES forms(id) ON DELETE CASCADE,
+    spreadsheet_id VARCHAR(255) NOT NULL,
+    sheet_name VARCHAR(100) NOT NULL,
+    refresh_token TEXT NOT NULL,
+    with_score BOOLEAN NOT NULL,
+    -- Element ids in sheet column order, and the header row last written
+    columns JSONB NOT NULL DEFAULT '[]',
+    headers JSONB NOT NULL DEFAULT '[]',
+    last_synced_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- When the response was appended to the form's sheet
+ALTER TABLE form_responses ADD COLUMN sheet_synced_at TIMESTAMPTZ;
+
+CREATE INDEX idx_form_responses_sheet_pending ON form_responses(form_id, created_at)
+    WHERE sheet_synced_at IS NULL;
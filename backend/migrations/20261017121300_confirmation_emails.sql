@@ -0,0 +1,14 @@
+-- The email sent to whoever answered a form's email question
+CREATE TABLE confirmation_emails (
+    form_id UUID PRIMARY KEY REFERENCES forms(id) ON DELETE CASCADE,
+    is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- The email question to reply to; NULL means the form's first one. Not a
+    -- foreign key: restoring a version recreates elements under the same ids
+    email_element_id UUID,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    -- Append a copy of the respondent's answers to the body
+    include_answers BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,13 @@
+-- Recent response activity per form, for live feeds. Ids are the feed's
+-- event ids, so a reconnecting client can ask for everything after the last
+-- one it saw. Old events are pruned.
+CREATE TABLE response_events (
+    id BIGSERIAL PRIMARY KEY,
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    event VARCHAR(50) NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_response_events_form ON response_events(form_id, id);
+CREATE INDEX idx_response_events_created ON response_events(created_at);
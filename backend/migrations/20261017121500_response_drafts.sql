@@ -0,0 +1,20 @@
+-- Partly filled in responses a respondent can come back to. Kept apart from
+-- form_responses so listings, exports and analytics never see them.
+CREATE TABLE response_drafts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    -- Signed-in respondents have one draft per form
+    respondent_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 of the resume token given to anonymous respondents
+    resume_token_hash VARCHAR(64) UNIQUE,
+    response_data JSONB NOT NULL DEFAULT '{}',
+    pages_reached UUID[],
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (respondent_id IS NOT NULL OR resume_token_hash IS NOT NULL)
+);
+
+CREATE UNIQUE INDEX idx_response_drafts_respondent ON response_drafts(form_id, respondent_id)
+    WHERE respondent_id IS NOT NULL;
+CREATE INDEX idx_response_drafts_expires ON response_drafts(expires_at);
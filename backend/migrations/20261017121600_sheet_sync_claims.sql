@@ -0,0 +1,4 @@
+-- Until when a sync is appending the response to the form's sheet. Syncs
+-- claim responses rather than locking the connection, so nothing is held
+-- open while Google is called; a claim left by a crashed server runs out.
+ALTER TABLE form_responses ADD COLUMN sheet_claimed_until TIMESTAMPTZ;
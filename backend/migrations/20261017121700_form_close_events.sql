@@ -0,0 +1,3 @@
+-- When form.closed was sent for the form's current closing. Cleared once the
+-- form takes responses again, so the next closing is announced too.
+ALTER TABLE forms ADD COLUMN close_announced_at TIMESTAMPTZ;
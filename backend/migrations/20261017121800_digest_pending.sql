@@ -0,0 +1,6 @@
+-- Set when a response is stored while its form's owner gets digests, and
+-- cleared by the digest that reports it. Unlike a window over created_at, a
+-- response whose transaction commits late still makes the next digest.
+ALTER TABLE form_responses ADD COLUMN digest_pending BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX idx_form_responses_digest_pending ON form_responses(form_id) WHERE digest_pending;
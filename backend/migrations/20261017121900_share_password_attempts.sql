@@ -0,0 +1,4 @@
+-- Wrong passwords tried against a password share since the last right one.
+-- Past a limit the share is locked for a while after every further miss.
+ALTER TABLE form_shares ADD COLUMN failed_password_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE form_shares ADD COLUMN password_locked_until TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+-- Recent mail per recipient and per form, counted before queueing more
+CREATE INDEX idx_email_outbox_to_address ON email_outbox(lower(to_address), created_at);
+CREATE INDEX idx_email_outbox_form_id ON email_outbox(form_id, created_at);
@@ -0,0 +1,4 @@
+-- Per-form hash of the device that started an anonymous draft, as on
+-- form_responses, so one device can't pile up drafts
+ALTER TABLE response_drafts ADD COLUMN client_key VARCHAR(64);
+CREATE INDEX idx_response_drafts_client_key ON response_drafts(form_id, client_key) WHERE client_key IS NOT NULL;
@@ -0,0 +1,10 @@
+-- Short-lived, single-use tickets for opening a form's live feed from
+-- clients that can't send an Authorization header. Only the hash is stored.
+CREATE TABLE live_tickets (
+    ticket_hash VARCHAR(64) PRIMARY KEY,
+    form_id UUID NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_live_tickets_expires ON live_tickets(expires_at);
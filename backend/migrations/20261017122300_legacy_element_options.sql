@@ -0,0 +1,53 @@
+-- Options of rows that predate typed elements are reshaped to the typed
+-- schemas (see src/elements.rs) where the intent is clear
+
+-- Choice lists were sent as bare arrays, of strings or of label/value objects
+UPDATE form_elements e
+SET options = jsonb_build_object('choices', converted.choices)
+FROM (
+    SELECT e.id, jsonb_agg(choice ORDER BY position) AS choices
+    FROM form_elements e
+    CROSS JOIN LATERAL jsonb_array_elements(e.options) WITH ORDINALITY AS items(item, position)
+    CROSS JOIN LATERAL (
+        SELECT CASE jsonb_typeof(item)
+            WHEN 'string' THEN item #>> '{}'
+            WHEN 'number' THEN item #>> '{}'
+            WHEN 'object' THEN COALESCE(item ->> 'label', item ->> 'value', item ->> 'text')
+        END AS choice
+    ) named
+    WHERE e.element_type IN ('single_choice', 'multiple_choice', 'dropdown')
+    AND jsonb_typeof(e.options) = 'array'
+    GROUP BY e.id
+    HAVING bool_and(trim(COALESCE(choice, '')) <> '') AND COUNT(DISTINCT choice) = COUNT(*)
+) converted
+WHERE e.id = converted.id;
+
+-- Anything else that doesn't fit its type is flagged: the original is kept
+-- in legacy_options and the row falls back to default options until edited
+ALTER TABLE form_elements ADD COLUMN legacy_options JSONB;
+
+UPDATE form_elements
+SET legacy_options = options,
+    options = NULL
+WHERE options IS NOT NULL
+AND options <> 'null'::jsonb
+AND (
+    jsonb_typeof(options) <> 'object'
+    OR (options ? 'choices' AND jsonb_typeof(options -> 'choices') <> 'array')
+    OR EXISTS (
+        SELECT 1 FROM jsonb_object_keys(options) AS keys(name)
+        WHERE name <> ALL (CASE
+            WHEN element_type IN ('short_text', 'long_text', 'email')
+                THEN ARRAY['min_length', 'max_length', 'placeholder']
+            WHEN element_type = 'number'
+                THEN ARRAY['min', 'max', 'integer_only']
+            WHEN element_type IN ('single_choice', 'multiple_choice', 'dropdown')
+                THEN ARRAY['choices', 'allow_other', 'min_selections', 'max_selections']
+            WHEN element_type = 'date' THEN ARRAY['min_date', 'max_date']
+            WHEN element_type = 'time' THEN ARRAY['min_time', 'max_time']
+            WHEN element_type = 'rating' THEN ARRAY['max']
+            WHEN element_type = 'linear_scale' THEN ARRAY['min', 'max', 'min_label', 'max_label']
+            WHEN element_type = 'file_upload' THEN ARRAY['max_size_bytes', 'allowed_mime_types', 'max_files']
+        END)
+    )
+);
@@ -0,0 +1,117 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Identifies the device an anonymous respondent is submitting from by its
+/// IP address. Only a per-form hash of it is ever stored.
+#[derive(Debug, Clone)]
+pub struct ClientId(Option<String>);
+
+/// Proxies whose `X-Forwarded-For` is believed. Requests from anywhere else
+/// are identified by the connecting address alone.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientId
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            return Ok(ClientId(None));
+        };
+        let trusted = parts
+            .extensions
+            .get::<TrustedProxies>()
+            .map(|proxies| proxies.0.as_slice())
+            .unwrap_or_default();
+
+        let forwarded = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(ClientId(Some(format!("ip:{}", client_ip(peer.ip(), &forwarded, trusted)))))
+    }
+}
+
+// Walks X-Forwarded-For back from the connecting peer while each hop is a
+// trusted proxy. Anything a caller wrote further left is never reached, so
+// it can't be spoofed.
+fn client_ip(peer: IpAddr, forwarded: &str, trusted: &[IpAddr]) -> IpAddr {
+    let mut ip = peer;
+    for hop in forwarded.rsplit(',') {
+        if !trusted.contains(&ip) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+    }
+    ip
+}
+
+impl ClientId {
+    /// Hashed with the form id so keys can't be correlated across forms.
+    pub fn key_for(&self, form_id: Uuid) -> Option<String> {
+        self.0.as_ref().map(|id| {
+            let mut hasher = Sha256::new();
+            hasher.update(form_id.as_bytes());
+            hasher.update(id.as_bytes());
+            hex::encode(hasher.finalize())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_forwarded_for() {
+        assert_eq!(client_ip(ip("203.0.113.9"), "198.51.100.1", &[]), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn trusted_proxy_hop_is_followed() {
+        let trusted = [ip("10.0.0.2")];
+        assert_eq!(client_ip(ip("10.0.0.2"), "198.51.100.1", &trusted), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn spoofed_hops_left_of_the_proxy_are_not_reached() {
+        let trusted = [ip("10.0.0.2")];
+        let forwarded = "1.2.3.4, 198.51.100.1";
+        assert_eq!(client_ip(ip("10.0.0.2"), forwarded, &trusted), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn chained_trusted_proxies_are_walked() {
+        let trusted = [ip("10.0.0.2"), ip("10.0.0.3")];
+        let forwarded = "198.51.100.1, 10.0.0.3";
+        assert_eq!(client_ip(ip("10.0.0.2"), forwarded, &trusted), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn garbage_hop_stops_at_the_proxy() {
+        let trusted = [ip("10.0.0.2")];
+        assert_eq!(client_ip(ip("10.0.0.2"), "not-an-ip", &trusted), ip("10.0.0.2"));
+    }
+}
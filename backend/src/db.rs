@@ -1,12 +1,47 @@
 use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
 
-pub async fn create_pool() -> Result<PgPool, sqlx::Error> {
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set");
+use crate::models::{FormElement, FormSection};
 
+pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
     PgPoolOptions::new()
         .max_connections(5)
-        .connect(&database_url)
+        .connect(database_url)
         .await
-}
\ No newline at end of file
+}
+
+// Form columns plus how many more responses it can take, for forms with a cap.
+// GREATEST ignores NULLs, so uncapped forms are handled separately.
+pub const FORM_WITH_REMAINING: &str = "*, CASE WHEN max_responses IS NULL THEN NULL ELSE GREATEST(max_responses - (
+    SELECT COUNT(*) FROM form_responses r WHERE r.form_id = forms.id
+), 0) END AS remaining_responses";
+
+// Elements in the order a respondent sees them: unsectioned elements first,
+// then each section in turn
+pub async fn fetch_elements(
+    executor: impl PgExecutor<'_>,
+    form_id: Uuid,
+) -> Result<Vec<FormElement>, sqlx::Error> {
+    sqlx::query_as::<_, FormElement>(
+        "SELECT e.* FROM form_elements e
+         LEFT JOIN form_sections s ON s.id = e.section_id
+         WHERE e.form_id = $1
+         ORDER BY s.order_index NULLS FIRST, e.order_index"
+    )
+    .bind(form_id)
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn fetch_sections(
+    executor: impl PgExecutor<'_>,
+    form_id: Uuid,
+) -> Result<Vec<FormSection>, sqlx::Error> {
+    sqlx::query_as::<_, FormSection>(
+        "SELECT * FROM form_sections WHERE form_id = $1 ORDER BY order_index"
+    )
+    .bind(form_id)
+    .fetch_all(executor)
+    .await
+}
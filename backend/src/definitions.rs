@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgConnection};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    db::{fetch_elements, fetch_sections, FORM_WITH_REMAINING},
+    models::{CreateFormElement, Form},
+};
+
+/// Version of the exported document format. Bump it whenever a change would
+/// stop older documents from importing as they did before.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A form definition as exported to and imported from JSON.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormDocument {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub exported_at: Option<OffsetDateTime>,
+    pub form: FormDefinition,
+}
+
+/// Everything needed to recreate a form: its settings, sections and elements.
+/// Ids only link sections, elements and logic within the definition; new ids
+/// are issued whenever it is instantiated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormDefinition {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub settings: FormSettings,
+    #[serde(default)]
+    pub sections: Vec<SectionDefinition>,
+    #[serde(default)]
+    pub elements: Vec<ElementDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormSettings {
+    pub is_public: bool,
+    pub allow_anonymous: bool,
+    pub opens_at: Option<OffsetDateTime>,
+    pub closes_at: Option<OffsetDateTime>,
+    pub closed_message: Option<String>,
+    pub max_responses: Option<i32>,
+    pub max_responses_per_respondent: Option<i32>,
+    pub max_responses_per_client: Option<i32>,
+    pub is_quiz: bool,
+    pub show_score_immediately: bool,
+}
+
+impl Default for FormSettings {
+    fn default() -> Self {
+        Self {
+            is_public: false,
+            allow_anonymous: true,
+            opens_at: None,
+            closes_at: None,
+            closed_message: None,
+            max_responses: None,
+            max_responses_per_respondent: None,
+            max_responses_per_client: None,
+            is_quiz: false,
+            show_score_immediately: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionDefinition {
+    pub id: Uuid,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub order_index: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementDefinition {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub element: CreateFormElement,
+}
+
+impl FormDefinition {
+    /// The live definition of `form`.
+    pub async fn load(conn: &mut PgConnection, form: &Form) -> Result<Self, sqlx::Error> {
+        let sections = fetch_sections(&mut *conn, form.id).await?;
+        let elements = fetch_elements(&mut *conn, form.id).await?;
+
+        Ok(Self {
+            title: form.title.clone(),
+            description: form.description.clone(),
+            settings: FormSettings {
+                is_public: form.is_public,
+                allow_anonymous: form.allow_anonymous,
+                opens_at: form.opens_at,
+                closes_at: form.closes_at,
+                closed_message: form.closed_message.clone(),
+                max_responses: form.max_responses,
+                max_responses_per_respondent: form.max_responses_per_respondent,
+                max_responses_per_client: form.max_responses_per_client,
+                is_quiz: form.is_quiz,
+                show_score_immediately: form.show_score_immediately,
+            },
+            sections: sections
+                .into_iter()
+                .map(|section| SectionDefinition {
+                    id: section.id,
+                    title: section.title,
+                    description: section.description,
+                    order_index: section.order_index,
+                })
+                .collect(),
+            elements: elements
+                .into_iter()
+                .map(|element| ElementDefinition {
+                    id: element.id,
+                    element: element.into(),
+                })
+                .collect(),
+        })
+    }
+
+    /// A copy with fresh ids, with section and logic references pointed at
+    /// them. Ids in `keep` stay as they are.
+    pub fn with_new_ids(&self, keep: &HashSet<Uuid>) -> Self {
+        let ids: HashMap<Uuid, Uuid> = self
+            .sections
+            .iter()
+            .map(|section| section.id)
+            .chain(self.elements.iter().map(|element| element.id))
+            .map(|id| (id, if keep.contains(&id) { id } else { Uuid::new_v4() }))
+            .collect();
+
+        let mut copy = self.clone();
+        for section in &mut copy.sections {
+            section.id = ids[&section.id];
+        }
+        for definition in &mut copy.elements {
+            definition.id = ids[&definition.id];
+            let element = &mut definition.element;
+            element.section_id = element.section_id.map(|id| ids.get(&id).copied().unwrap_or(id));
+            if let Some(logic) = &mut element.logic {
+                logic.remap(&ids);
+            }
+        }
+
+        copy
+    }
+
+    /// Creates a new draft form owned by `user_id` from this definition. The
+    /// definition is expected to be valid already.
+    pub async fn instantiate(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<Form, sqlx::Error> {
+        let definition = self.with_new_ids(&HashSet::new());
+        let settings = &definition.settings;
+
+        let form = sqlx::query_as::<_, Form>(
+            "INSERT INTO forms
+             (user_id, title, description, is_public, allow_anonymous, opens_at, closes_at, closed_message,
+              max_responses, max_responses_per_respondent, max_responses_per_client, is_quiz, show_score_immediately)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+             RETURNING *, max_responses::BIGINT AS remaining_responses"
+        )
+        .bind(user_id)
+        .bind(&definition.title)
+        .bind(&definition.description)
+        .bind(settings.is_public)
+        .bind(settings.allow_anonymous)
+        .bind(settings.opens_at)
+        .bind(settings.closes_at)
+        .bind(&settings.closed_message)
+        .bind(settings.max_responses)
+        .bind(settings.max_responses_per_respondent)
+        .bind(settings.max_responses_per_client)
+        .bind(settings.is_quiz)
+        .bind(settings.show_score_immediately)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        definition.insert_layout(conn, form.id).await?;
+
+        Ok(form)
+    }
+
+    /// Overwrites an existing form's settings, sections and elements. Sections
+    /// and elements whose ids already belong to the form keep them, so earlier
+    /// answers still line up; everything else gets a new id.
+    pub async fn replace(&self, conn: &mut PgConnection, form_id: Uuid) -> Result<Form, sqlx::Error> {
+        let existing: HashSet<Uuid> = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM form_sections WHERE form_id = $1
+             UNION ALL
+             SELECT id FROM form_elements WHERE form_id = $1"
+        )
+        .bind(form_id)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .collect();
+
+        let definition = self.with_new_ids(&existing);
+        let settings = &definition.settings;
+
+        sqlx::query("DELETE FROM form_elements WHERE form_id = $1")
+            .bind(form_id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM form_sections WHERE form_id = $1")
+            .bind(form_id)
+            .execute(&mut *conn)
+            .await?;
+
+        definition.insert_layout(conn, form_id).await?;
+
+        sqlx::query_as::<_, Form>(&format!(
+            "UPDATE forms
+             SET title = $1, description = $2, is_public = $3, allow_anonymous = $4, opens_at = $5,
+                 closes_at = $6, closed_message = $7, max_responses = $8, max_responses_per_respondent = $9,
+                 max_responses_per_client = $10, is_quiz = $11, show_score_immediately = $12, updated_at = NOW()
+             WHERE id = $13
+             RETURNING {}", FORM_WITH_REMAINING
+        ))
+        .bind(&definition.title)
+        .bind(&definition.description)
+        .bind(settings.is_public)
+        .bind(settings.allow_anonymous)
+        .bind(settings.opens_at)
+        .bind(settings.closes_at)
+        .bind(&settings.closed_message)
+        .bind(settings.max_responses)
+        .bind(settings.max_responses_per_respondent)
+        .bind(settings.max_responses_per_client)
+        .bind(settings.is_quiz)
+        .bind(settings.show_score_immediately)
+        .bind(form_id)
+        .fetch_one(&mut *conn)
+        .await
+    }
+
+    async fn insert_layout(&self, conn: &mut PgConnection, form_id: Uuid) -> Result<(), sqlx::Error> {
+        for section in &self.sections {
+            sqlx::query(
+                "INSERT INTO form_sections (id, form_id, title, description, order_index)
+                 VALUES ($1, $2, $3, $4, $5)"
+            )
+            .bind(section.id)
+            .bind(form_id)
+            .bind(&section.title)
+            .bind(&section.description)
+            .bind(section.order_index)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for definition in &self.elements {
+            let element = &definition.element;
+            sqlx::query(
+                "INSERT INTO form_elements
+                 (id, form_id, section_id, element_type, question, required, options, order_index, logic,
+                  correct_answer, points)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+            )
+            .bind(definition.id)
+            .bind(form_id)
+            .bind(element.section_id)
+            .bind(element.element_type.as_str())
+            .bind(&element.question)
+            .bind(element.required)
+            .bind(&element.options)
+            .bind(element.order_index)
+            .bind(element.logic.as_ref().map(Json))
+            .bind(&element.correct_answer)
+            .bind(element.points)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A new resume token for an anonymous draft. Only its hash is stored.
+pub fn new_resume_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn resume_token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Deletes expired drafts for as long as the server runs. Expired drafts
+/// can't be fetched even before they're deleted.
+pub fn spawn_cleanup(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            let deleted = sqlx::query("DELETE FROM response_drafts WHERE expires_at <= NOW()")
+                .execute(&pool)
+                .await;
+            match deleted {
+                Ok(result) if result.rows_affected() > 0 => {
+                    tracing::info!("deleted {} expired drafts", result.rows_affected());
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("deleting expired drafts failed: {}", e),
+            }
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,524 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::types::JsonValue;
+use time::{macros::format_description, Date, Time};
+
+use crate::{
+    error::{AppError, FieldError},
+    grading::validate_answer_key,
+    logic::{element_position, validate_logic},
+    models::{CreateFormElement, FormElement, FormSection},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementType {
+    ShortText,
+    LongText,
+    Email,
+    Number,
+    SingleChoice,
+    MultipleChoice,
+    Dropdown,
+    Date,
+    Time,
+    Rating,
+    LinearScale,
+    FileUpload,
+}
+
+impl ElementType {
+    pub const ALL: [ElementType; 12] = [
+        ElementType::ShortText,
+        ElementType::LongText,
+        ElementType::Email,
+        ElementType::Number,
+        ElementType::SingleChoice,
+        ElementType::MultipleChoice,
+        ElementType::Dropdown,
+        ElementType::Date,
+        ElementType::Time,
+        ElementType::Rating,
+        ElementType::LinearScale,
+        ElementType::FileUpload,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElementType::ShortText => "short_text",
+            ElementType::LongText => "long_text",
+            ElementType::Email => "email",
+            ElementType::Number => "number",
+            ElementType::SingleChoice => "single_choice",
+            ElementType::MultipleChoice => "multiple_choice",
+            ElementType::Dropdown => "dropdown",
+            ElementType::Date => "date",
+            ElementType::Time => "time",
+            ElementType::Rating => "rating",
+            ElementType::LinearScale => "linear_scale",
+            ElementType::FileUpload => "file_upload",
+        }
+    }
+}
+
+impl TryFrom<String> for ElementType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        ElementType::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("unknown element type `{}`", value))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextOptions {
+    pub min_length: Option<u32>,
+    pub max_length: Option<u32>,
+    pub placeholder: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NumberOptions {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub integer_only: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChoiceOptions {
+    #[serde(default)]
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub allow_other: bool,
+    pub min_selections: Option<u32>,
+    pub max_selections: Option<u32>,
+}
+
+/// Bounds are `YYYY-MM-DD` strings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateOptions {
+    pub min_date: Option<String>,
+    pub max_date: Option<String>,
+}
+
+/// Bounds are 24-hour `HH:MM` strings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeOptions {
+    pub min_time: Option<String>,
+    pub max_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RatingOptions {
+    #[serde(default = "default_rating_max")]
+    pub max: i64,
+}
+
+impl Default for RatingOptions {
+    fn default() -> Self {
+        Self { max: default_rating_max() }
+    }
+}
+
+fn default_rating_max() -> i64 {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinearScaleOptions {
+    #[serde(default = "default_scale_min")]
+    pub min: i64,
+    #[serde(default = "default_scale_max")]
+    pub max: i64,
+    pub min_label: Option<String>,
+    pub max_label: Option<String>,
+}
+
+impl Default for LinearScaleOptions {
+    fn default() -> Self {
+        Self {
+            min: default_scale_min(),
+            max: default_scale_max(),
+            min_label: None,
+            max_label: None,
+        }
+    }
+}
+
+fn default_scale_min() -> i64 {
+    1
+}
+
+fn default_scale_max() -> i64 {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileUploadOptions {
+    pub max_size_bytes: Option<u64>,
+    #[serde(default)]
+    pub allowed_mime_types: Vec<String>,
+    #[serde(default = "default_max_files")]
+    pub max_files: u32,
+}
+
+impl Default for FileUploadOptions {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: None,
+            allowed_mime_types: Vec::new(),
+            max_files: default_max_files(),
+        }
+    }
+}
+
+fn default_max_files() -> u32 {
+    1
+}
+
+/// The options of an element, parsed according to its type.
+#[derive(Debug, Clone)]
+pub enum ElementOptions {
+    Text(TextOptions),
+    Number(NumberOptions),
+    Choice(ChoiceOptions),
+    Date(DateOptions),
+    Time(TimeOptions),
+    Rating(RatingOptions),
+    LinearScale(LinearScaleOptions),
+    FileUpload(FileUploadOptions),
+}
+
+impl ElementOptions {
+    /// Parses `options` with the schema for `element_type` and checks that the
+    /// values are consistent. Errors are reported against `options.*` fields.
+    pub fn parse(
+        element_type: ElementType,
+        options: Option<&JsonValue>,
+    ) -> Result<Self, Vec<FieldError>> {
+        let parsed = match element_type {
+            ElementType::ShortText | ElementType::LongText | ElementType::Email => {
+                ElementOptions::Text(parse_as(options)?)
+            }
+            ElementType::Number => ElementOptions::Number(parse_as(options)?),
+            ElementType::SingleChoice | ElementType::MultipleChoice | ElementType::Dropdown => {
+                ElementOptions::Choice(parse_as(options)?)
+            }
+            ElementType::Date => ElementOptions::Date(parse_as(options)?),
+            ElementType::Time => ElementOptions::Time(parse_as(options)?),
+            ElementType::Rating => ElementOptions::Rating(parse_as(options)?),
+            ElementType::LinearScale => ElementOptions::LinearScale(parse_as(options)?),
+            ElementType::FileUpload => ElementOptions::FileUpload(parse_as(options)?),
+        };
+
+        let errors = parsed.check(element_type);
+        if errors.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check(&self, element_type: ElementType) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        match self {
+            ElementOptions::Text(opts) => {
+                if opts.max_length == Some(0) {
+                    errors.push(FieldError::new("options.max_length", "must be greater than 0"));
+                }
+                if let (Some(min), Some(max)) = (opts.min_length, opts.max_length) {
+                    if min > max {
+                        errors.push(FieldError::new(
+                            "options.min_length",
+                            "must not be greater than max_length",
+                        ));
+                    }
+                }
+            }
+            ElementOptions::Number(opts) => {
+                if let (Some(min), Some(max)) = (opts.min, opts.max) {
+                    if min > max {
+                        errors.push(FieldError::new("options.min", "must not be greater than max"));
+                    }
+                }
+            }
+            ElementOptions::Choice(opts) => {
+                if opts.choices.is_empty() {
+                    errors.push(FieldError::new("options.choices", "at least one choice is required"));
+                }
+                for (i, choice) in opts.choices.iter().enumerate() {
+                    if choice.trim().is_empty() {
+                        errors.push(FieldError::new(
+                            format!("options.choices[{}]", i),
+                            "must not be empty",
+                        ));
+                    } else if opts.choices[..i].contains(choice) {
+                        errors.push(FieldError::new(
+                            format!("options.choices[{}]", i),
+                            "duplicate choice",
+                        ));
+                    }
+                }
+
+                if element_type == ElementType::MultipleChoice {
+                    if let (Some(min), Some(max)) = (opts.min_selections, opts.max_selections) {
+                        if min > max {
+                            errors.push(FieldError::new(
+                                "options.min_selections",
+                                "must not be greater than max_selections",
+                            ));
+                        }
+                    }
+                    if opts.max_selections == Some(0) {
+                        errors.push(FieldError::new("options.max_selections", "must be greater than 0"));
+                    }
+                } else {
+                    if opts.min_selections.is_some() {
+                        errors.push(FieldError::new(
+                            "options.min_selections",
+                            "only applies to multiple_choice elements",
+                        ));
+                    }
+                    if opts.max_selections.is_some() {
+                        errors.push(FieldError::new(
+                            "options.max_selections",
+                            "only applies to multiple_choice elements",
+                        ));
+                    }
+                }
+
+                if element_type == ElementType::Dropdown && opts.allow_other {
+                    errors.push(FieldError::new(
+                        "options.allow_other",
+                        "is not supported for dropdown elements",
+                    ));
+                }
+            }
+            ElementOptions::Date(opts) => {
+                let min = check_bound(&mut errors, "options.min_date", opts.min_date.as_deref(), parse_date);
+                let max = check_bound(&mut errors, "options.max_date", opts.max_date.as_deref(), parse_date);
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        errors.push(FieldError::new("options.min_date", "must not be after max_date"));
+                    }
+                }
+            }
+            ElementOptions::Time(opts) => {
+                let min = check_bound(&mut errors, "options.min_time", opts.min_time.as_deref(), parse_time);
+                let max = check_bound(&mut errors, "options.max_time", opts.max_time.as_deref(), parse_time);
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        errors.push(FieldError::new("options.min_time", "must not be after max_time"));
+                    }
+                }
+            }
+            ElementOptions::Rating(opts) => {
+                if !(2..=10).contains(&opts.max) {
+                    errors.push(FieldError::new("options.max", "must be between 2 and 10"));
+                }
+            }
+            ElementOptions::LinearScale(opts) => {
+                if !(0..=1).contains(&opts.min) {
+                    errors.push(FieldError::new("options.min", "must be 0 or 1"));
+                }
+                if !(2..=10).contains(&opts.max) {
+                    errors.push(FieldError::new("options.max", "must be between 2 and 10"));
+                }
+            }
+            ElementOptions::FileUpload(opts) => {
+                if opts.max_size_bytes == Some(0) {
+                    errors.push(FieldError::new("options.max_size_bytes", "must be greater than 0"));
+                }
+                if !(1..=10).contains(&opts.max_files) {
+                    errors.push(FieldError::new("options.max_files", "must be between 1 and 10"));
+                }
+                for (i, mime) in opts.allowed_mime_types.iter().enumerate() {
+                    let valid = mime
+                        .split_once('/')
+                        .map(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty())
+                        .unwrap_or(false);
+                    if !valid {
+                        errors.push(FieldError::new(
+                            format!("options.allowed_mime_types[{}]", i),
+                            "must look like `type/subtype` or `type/*`",
+                        ));
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+fn parse_as<T: DeserializeOwned + Default>(options: Option<&JsonValue>) -> Result<T, Vec<FieldError>> {
+    match options {
+        None | Some(JsonValue::Null) => Ok(T::default()),
+        Some(value) => T::deserialize(value).map_err(|e| vec![FieldError::new("options", e.to_string())]),
+    }
+}
+
+fn check_bound<T>(
+    errors: &mut Vec<FieldError>,
+    field: &str,
+    value: Option<&str>,
+    parse: fn(&str) -> Option<T>,
+) -> Option<T> {
+    let value = value?;
+    let parsed = parse(value);
+    if parsed.is_none() {
+        errors.push(FieldError::new(field, format!("`{}` is not in the expected format", value)));
+    }
+    parsed
+}
+
+pub fn parse_date(value: &str) -> Option<Date> {
+    Date::parse(value, format_description!("[year]-[month]-[day]")).ok()
+}
+
+pub fn parse_time(value: &str) -> Option<Time> {
+    Time::parse(value, format_description!("[hour]:[minute]")).ok()
+}
+
+/// Validates an element definition before it is written to `form_elements`.
+/// `siblings` are the other elements of the form and `sections` its sections.
+pub fn validate_element(
+    payload: &CreateFormElement,
+    siblings: &[FormElement],
+    sections: &[FormSection],
+) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if payload.question.trim().is_empty() {
+        errors.push(FieldError::new("question", "must not be empty"));
+    }
+    if payload.order_index < 0 {
+        errors.push(FieldError::new("order_index", "must not be negative"));
+    }
+    if let Some(section_id) = payload.section_id {
+        if !sections.iter().any(|section| section.id == section_id) {
+            errors.push(FieldError::new("section_id", "section is not part of this form"));
+        }
+    }
+    if let Some(logic) = &payload.logic {
+        let position = element_position(payload.section_id, payload.order_index, sections);
+        errors.extend(validate_logic(logic, position, siblings, sections));
+    }
+    errors.extend(validate_answer_key(
+        payload.element_type,
+        payload.options.as_ref(),
+        payload.correct_answer.as_ref(),
+        payload.points,
+    ));
+
+    match ElementOptions::parse(payload.element_type, payload.options.as_ref()) {
+        Ok(_) if errors.is_empty() => Ok(()),
+        Ok(_) => Err(AppError::FieldErrors(errors)),
+        Err(option_errors) => {
+            errors.extend(option_errors);
+            Err(AppError::FieldErrors(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn error_fields(element_type: ElementType, options: JsonValue) -> Vec<String> {
+        match ElementOptions::parse(element_type, Some(&options)) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.field).collect(),
+        }
+    }
+
+    #[test]
+    fn missing_options_use_defaults() {
+        let Ok(ElementOptions::Rating(rating)) = ElementOptions::parse(ElementType::Rating, None) else {
+            panic!("rating options should parse");
+        };
+        assert_eq!(rating.max, 5);
+
+        let Ok(ElementOptions::FileUpload(files)) = ElementOptions::parse(ElementType::FileUpload, Some(&JsonValue::Null)) else {
+            panic!("file upload options should parse");
+        };
+        assert_eq!(files.max_files, 1);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert_eq!(error_fields(ElementType::ShortText, json!({ "maxLength": 5 })), ["options"]);
+    }
+
+    #[test]
+    fn choices_must_be_present_unique_and_non_empty() {
+        assert_eq!(error_fields(ElementType::SingleChoice, json!({ "choices": [] })), ["options.choices"]);
+        assert_eq!(
+            error_fields(ElementType::SingleChoice, json!({ "choices": ["A", " ", "A"] })),
+            ["options.choices[1]", "options.choices[2]"]
+        );
+    }
+
+    #[test]
+    fn selection_limits_only_apply_to_multiple_choice() {
+        let options = json!({ "choices": ["A", "B"], "min_selections": 1 });
+        assert!(error_fields(ElementType::MultipleChoice, options.clone()).is_empty());
+        assert_eq!(error_fields(ElementType::Dropdown, options), ["options.min_selections"]);
+        assert_eq!(
+            error_fields(ElementType::MultipleChoice, json!({ "choices": ["A"], "min_selections": 2, "max_selections": 1 })),
+            ["options.min_selections"]
+        );
+    }
+
+    #[test]
+    fn dropdowns_cannot_allow_other() {
+        assert_eq!(
+            error_fields(ElementType::Dropdown, json!({ "choices": ["A"], "allow_other": true })),
+            ["options.allow_other"]
+        );
+    }
+
+    #[test]
+    fn bounds_must_parse_and_be_ordered() {
+        assert_eq!(error_fields(ElementType::Date, json!({ "min_date": "tomorrow" })), ["options.min_date"]);
+        assert_eq!(
+            error_fields(ElementType::Date, json!({ "min_date": "2024-02-01", "max_date": "2024-01-01" })),
+            ["options.min_date"]
+        );
+        assert_eq!(
+            error_fields(ElementType::Time, json!({ "min_time": "18:00", "max_time": "09:00" })),
+            ["options.min_time"]
+        );
+        assert_eq!(error_fields(ElementType::Number, json!({ "min": 5, "max": 1 })), ["options.min"]);
+    }
+
+    #[test]
+    fn scales_are_limited() {
+        assert_eq!(error_fields(ElementType::Rating, json!({ "max": 11 })), ["options.max"]);
+        assert_eq!(
+            error_fields(ElementType::LinearScale, json!({ "min": 2, "max": 1 })),
+            ["options.min", "options.max"]
+        );
+    }
+
+    #[test]
+    fn mime_patterns_need_a_subtype() {
+        assert_eq!(
+            error_fields(ElementType::FileUpload, json!({ "allowed_mime_types": ["image/*", "pdf"] })),
+            ["options.allowed_mime_types[1]"]
+        );
+    }
+}
@@ -3,9 +3,25 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use serde::Serialize;
 use serde_json::json;
 use thiserror::Error;
 
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Authentication failed")]
@@ -19,7 +35,22 @@ pub enum AppError {
     
     #[error("Validation error: {0}")]
     ValidationError(String),
+
+    #[error("Validation failed")]
+    FieldErrors(Vec<FieldError>),
     
+    #[error("{0}")]
+    FormClosed(String),
+
+    #[error("{0}")]
+    ResponseLimitReached(String),
+
+    #[error("This form is password protected")]
+    SharePasswordRequired,
+
+    #[error("{0}")]
+    TooManyAttempts(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
     
@@ -37,6 +68,18 @@ impl IntoResponse for AppError {
             AppError::AuthorizationError => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()),
             AppError::ValidationError(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::FieldErrors(errors) => {
+                let body = Json(json!({
+                    "error": "Validation failed",
+                    "fields": errors
+                }));
+
+                return (StatusCode::BAD_REQUEST, body).into_response();
+            }
+            AppError::FormClosed(msg) => (StatusCode::FORBIDDEN, msg),
+            AppError::ResponseLimitReached(msg) => (StatusCode::CONFLICT, msg),
+            AppError::SharePasswordRequired => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::TooManyAttempts(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::PaymentError(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::InternalError => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
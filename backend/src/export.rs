@@ -0,0 +1,734 @@
+use std::{
+    io::{self, Read, Seek, Write},
+    sync::{Arc, Mutex},
+};
+
+use axum::body::StreamBody;
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+
+use parquet::{
+    basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType},
+    data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    format::MicroSeconds,
+    schema::types::Type as SchemaType,
+};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+use serde_json::Value as JsonValue;
+use sqlx::{PgConnection, PgPool};
+use time::{format_description::{well_known::Rfc3339, OwnedFormatItem}, OffsetDateTime};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    elements::ElementType,
+    error::{AppError, FieldError},
+    versions::{export_columns, ExportColumn},
+};
+
+// Excel's limits
+pub const XLSX_MAX_ROWS: u32 = 1_048_576;
+const XLSX_MAX_STRING: usize = 32_767;
+// Column widths, in characters
+const XLSX_MIN_WIDTH: usize = 10;
+const XLSX_MAX_WIDTH: usize = 50;
+const XLSX_DATE_WIDTH: usize = 20;
+// Rows buffered before a Parquet row group is written
+const PARQUET_ROW_GROUP: usize = 10_000;
+// Bytes of output sent to the client at a time, and how many chunks may wait
+// on a slow client before reading more rows
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+const EXPORT_BUFFERED_CHUNKS: usize = 4;
+// Rows handed to the writer per trip to the blocking pool
+const EXPORT_WRITE_BATCH: usize = 500;
+
+/// A response joined with what we know about who sent it.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ExportRow {
+    pub id: Uuid,
+    pub created_at: OffsetDateTime,
+    pub respondent_id: Option<Uuid>,
+    // The account's email, or the invitee's for invite-only links
+    pub respondent_email: Option<String>,
+    pub respondent_name: Option<String>,
+    pub response_data: JsonValue,
+    pub score: Option<i32>,
+    pub max_score: Option<i32>,
+}
+
+/// Binds the form id and, optionally, the only responses to include.
+pub const EXPORT_ROWS: &str =
+    "SELECT r.id, r.created_at, r.respondent_id, COALESCE(u.email, i.email) AS respondent_email,
+            u.full_name AS respondent_name, r.response_data, r.score, r.max_score
+     FROM form_responses r
+     LEFT JOIN users u ON u.id = r.respondent_id
+     LEFT JOIN share_invites i ON i.response_id = r.id
+     WHERE r.form_id = $1 AND ($2::uuid[] IS NULL OR r.id = ANY($2))
+     ORDER BY r.created_at, r.id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(ExportFormat::Csv),
+            "xlsx" => Some(ExportFormat::Xlsx),
+            "ndjson" | "jsonl" => Some(ExportFormat::Ndjson),
+            "parquet" => Some(ExportFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DateFormat {
+    Rfc3339,
+    Unix,
+    Custom(OwnedFormatItem),
+}
+
+impl DateFormat {
+    /// `rfc3339` (the default), `unix`, or a `time` format description such
+    /// as `[year]-[month]-[day] [hour]:[minute]`. Times are always UTC.
+    pub fn parse(format: Option<&str>) -> Result<Self, FieldError> {
+        match format {
+            None | Some("rfc3339") => Ok(DateFormat::Rfc3339),
+            Some("unix") => Ok(DateFormat::Unix),
+            Some(custom) => time::format_description::parse_owned::<2>(custom)
+                .map(DateFormat::Custom)
+                .map_err(|e| FieldError::new("date_format", e.to_string())),
+        }
+    }
+
+    pub fn format(&self, at: OffsetDateTime) -> String {
+        match self {
+            DateFormat::Rfc3339 => at.format(&Rfc3339).unwrap_or_default(),
+            DateFormat::Unix => at.unix_timestamp().to_string(),
+            DateFormat::Custom(format) => at.format(format).unwrap_or_default(),
+        }
+    }
+}
+
+/// What a column holds, so typed formats can pick a matching cell or field
+/// type. Every value in a column is either of its kind or empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    Integer,
+    Number,
+    Timestamp,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExportValue {
+    Empty,
+    Text(String),
+    Integer(i64),
+    Number(f64),
+    Timestamp(OffsetDateTime),
+}
+
+impl ExportValue {
+    pub fn to_text(&self, date_format: &DateFormat) -> String {
+        match self {
+            ExportValue::Empty => String::new(),
+            ExportValue::Text(text) => text.clone(),
+            ExportValue::Integer(value) => value.to_string(),
+            ExportValue::Number(value) => value.to_string(),
+            ExportValue::Timestamp(at) => date_format.format(*at),
+        }
+    }
+
+    /// Numbers stay numbers, and so do unix timestamps.
+    pub fn to_json(&self, date_format: &DateFormat) -> JsonValue {
+        match self {
+            ExportValue::Empty => JsonValue::Null,
+            ExportValue::Integer(value) => (*value).into(),
+            ExportValue::Number(value) => (*value).into(),
+            ExportValue::Timestamp(at) if matches!(date_format, DateFormat::Unix) => at.unix_timestamp().into(),
+            other => other.to_text(date_format).into(),
+        }
+    }
+}
+
+/// What goes in an export: which questions, in which order, and how to write
+/// the values that aren't answers.
+pub struct ExportLayout {
+    pub columns: Vec<ExportColumn>,
+    pub with_score: bool,
+    pub date_format: DateFormat,
+}
+
+impl ExportLayout {
+    /// `include_removed` also exports questions deleted since responses came in.
+    pub async fn load(
+        conn: &mut PgConnection,
+        form_id: Uuid,
+        is_quiz: bool,
+        date_format: Option<&str>,
+        include_removed: bool,
+    ) -> Result<Self, AppError> {
+        let date_format = DateFormat::parse(date_format).map_err(|e| AppError::FieldErrors(vec![e]))?;
+
+        let mut columns = export_columns(conn, form_id).await.map_err(AppError::DatabaseError)?;
+        if !include_removed {
+            columns.retain(|column| !column.removed);
+        }
+
+        Ok(Self {
+            columns,
+            with_score: is_quiz,
+            date_format,
+        })
+    }
+
+    /// Header and kind of every column, respondent details first.
+    pub fn fields(&self) -> Vec<(String, ColumnKind)> {
+        let mut fields = vec![
+            ("Response ID".to_string(), ColumnKind::Text),
+            ("Submitted At".to_string(), ColumnKind::Timestamp),
+            ("Respondent ID".to_string(), ColumnKind::Text),
+            ("Respondent Email".to_string(), ColumnKind::Text),
+            ("Respondent Name".to_string(), ColumnKind::Text),
+        ];
+        if self.with_score {
+            fields.push(("Score".to_string(), ColumnKind::Integer));
+            fields.push(("Max Score".to_string(), ColumnKind::Integer));
+        }
+
+        for column in &self.columns {
+            // A question could be called "Score"; keep every header unique
+            let label = if fields.iter().any(|(header, _)| *header == column.label) {
+                format!("{} [{}]", column.label, column.element_id)
+            } else {
+                column.label.clone()
+            };
+            fields.push((label, question_kind(column.element_type)));
+        }
+
+        fields
+    }
+
+    /// One value per field, answers flattened to their column's kind.
+    pub fn values(&self, row: &ExportRow) -> Vec<ExportValue> {
+        let text = |value: Option<String>| value.map(ExportValue::Text).unwrap_or(ExportValue::Empty);
+        let integer = |value: Option<i32>| value.map(|value| ExportValue::Integer(value.into())).unwrap_or(ExportValue::Empty);
+
+        let mut values = vec![
+            ExportValue::Text(row.id.to_string()),
+            ExportValue::Timestamp(row.created_at),
+            text(row.respondent_id.map(|id| id.to_string())),
+            text(row.respondent_email.clone()),
+            text(row.respondent_name.clone()),
+        ];
+        if self.with_score {
+            values.push(integer(row.score));
+            values.push(integer(row.max_score));
+        }
+        values.extend(self.columns.iter().map(|column| {
+            match row.response_data.get(column.element_id.to_string()) {
+                None | Some(JsonValue::Null) => ExportValue::Empty,
+                Some(answer) => match question_kind(column.element_type) {
+                    ColumnKind::Integer => answer.as_i64().map(ExportValue::Integer).unwrap_or(ExportValue::Empty),
+                    ColumnKind::Number => answer.as_f64().map(ExportValue::Number).unwrap_or(ExportValue::Empty),
+                    _ => ExportValue::Text(answer_text(answer)),
+                },
+            }
+        }));
+
+        values
+    }
+}
+
+fn question_kind(element_type: ElementType) -> ColumnKind {
+    match element_type {
+        ElementType::Number => ColumnKind::Number,
+        ElementType::Rating | ElementType::LinearScale => ColumnKind::Integer,
+        _ => ColumnKind::Text,
+    }
+}
+
+/// A single answer as spreadsheet text. Multi-select answers are joined with
+/// "; " so they stay in one cell.
+pub fn answer_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(text) => text.clone(),
+        JsonValue::Array(items) => items.iter().map(answer_text).collect::<Vec<_>>().join("; "),
+        other => other.to_string(),
+    }
+}
+
+fn export_error(format: ExportFormat, e: &dyn std::fmt::Display) -> AppError {
+    tracing::error!("failed to write {} export: {}", format.extension(), e);
+    AppError::InternalError
+}
+
+// Where the format writers put their bytes; drained as the export streams
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes rows in one of the export formats. The header goes out when the
+/// writer is created.
+pub struct ExportWriter {
+    format: ExportFormat,
+    layout: ExportLayout,
+    kinds: Vec<ColumnKind>,
+    output: Output,
+    inner: Inner,
+}
+
+enum Inner {
+    Csv(csv::Writer<Output>),
+    Ndjson {
+        headers: Vec<String>,
+    },
+    // An xlsx file is a zip archive, so nothing can be sent until it's
+    // complete. Rows go to a temporary file rather than memory meanwhile.
+    Xlsx {
+        workbook: Box<Workbook>,
+        // Header lengths, until the first row settles the column widths
+        widths: Vec<usize>,
+        row: u32,
+        date_format: Format,
+    },
+    Parquet {
+        writer: SerializedFileWriter<Output>,
+        pending: Vec<Vec<ExportValue>>,
+    },
+}
+
+impl ExportWriter {
+    /// `delimiter` only matters for CSV.
+    pub fn new(format: ExportFormat, layout: ExportLayout, delimiter: u8) -> Result<Self, AppError> {
+        let fields = layout.fields();
+        let kinds: Vec<ColumnKind> = fields.iter().map(|(_, kind)| *kind).collect();
+        let headers: Vec<String> = fields.into_iter().map(|(header, _)| header).collect();
+        let error = |e: &dyn std::fmt::Display| export_error(format, e);
+        let output = Output::default();
+
+        let inner = match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(output.clone());
+                writer.write_record(&headers).map_err(|e| error(&e))?;
+                Inner::Csv(writer)
+            }
+            ExportFormat::Ndjson => Inner::Ndjson { headers },
+            ExportFormat::Xlsx => {
+                let mut workbook = Workbook::new();
+                let worksheet = workbook.add_worksheet_with_constant_memory();
+                worksheet.set_name("Responses").map_err(|e| error(&e))?;
+                let bold = Format::new().set_bold();
+                for (column, header) in headers.iter().enumerate() {
+                    worksheet
+                        .write_string_with_format(0, column as u16, xlsx_text(header), &bold)
+                        .map_err(|e| error(&e))?;
+                }
+                worksheet.set_freeze_panes(1, 0).map_err(|e| error(&e))?;
+                Inner::Xlsx {
+                    workbook: Box::new(workbook),
+                    widths: headers.iter().map(|header| header.chars().count()).collect(),
+                    row: 1,
+                    date_format: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+                }
+            }
+            ExportFormat::Parquet => {
+                let schema = parquet_schema(&headers, &kinds).map_err(|e| error(&e))?;
+                let properties = WriterProperties::builder()
+                    .set_compression(parquet::basic::Compression::SNAPPY)
+                    .build();
+                let writer = SerializedFileWriter::new(output.clone(), Arc::new(schema), Arc::new(properties))
+                    .map_err(|e| error(&e))?;
+                Inner::Parquet {
+                    writer,
+                    pending: Vec::new(),
+                }
+            }
+        };
+
+        Ok(Self { format, layout, kinds, output, inner })
+    }
+
+    pub fn write(&mut self, row: &ExportRow) -> Result<(), AppError> {
+        let format = self.format;
+        let error = |e: &dyn std::fmt::Display| export_error(format, e);
+        let values = self.layout.values(row);
+        let date_format = &self.layout.date_format;
+
+        match &mut self.inner {
+            Inner::Csv(writer) => {
+                let cells = values.iter().map(|value| value.to_text(date_format));
+                writer.write_record(cells).map_err(|e| error(&e))?;
+            }
+            Inner::Ndjson { headers } => {
+                // Written by hand so keys keep the column order
+                let mut line = vec![b'{'];
+                for (index, (header, value)) in headers.iter().zip(&values).enumerate() {
+                    if index > 0 {
+                        line.push(b',');
+                    }
+                    serde_json::to_writer(&mut line, header).map_err(|e| error(&e))?;
+                    line.push(b':');
+                    serde_json::to_writer(&mut line, &value.to_json(date_format)).map_err(|e| error(&e))?;
+                }
+                line.extend_from_slice(b"}\n");
+                self.output.write_all(&line).map_err(|e| error(&e))?;
+            }
+            Inner::Xlsx { workbook, widths, row, date_format } => {
+                if *row >= XLSX_MAX_ROWS {
+                    return Err(AppError::ValidationError(
+                        "Too many responses for an Excel sheet; export as CSV instead".to_string(),
+                    ));
+                }
+                let worksheet = workbook.worksheet_from_index(0).map_err(|e| error(&e))?;
+                // Rows aren't kept around to autofit, so size columns by the
+                // header and first row
+                if *row == 1 {
+                    for (column, (width, value)) in widths.iter().zip(&values).enumerate() {
+                        let width = match value {
+                            ExportValue::Timestamp(_) => XLSX_DATE_WIDTH,
+                            other => other.to_text(&DateFormat::Rfc3339).chars().count(),
+                        }
+                        .max(*width)
+                        .clamp(XLSX_MIN_WIDTH, XLSX_MAX_WIDTH);
+                        worksheet.set_column_width(column as u16, width as f64).map_err(|e| error(&e))?;
+                    }
+                }
+                for (column, value) in values.iter().enumerate() {
+                    let column = column as u16;
+                    match value {
+                        ExportValue::Empty => Ok(&mut *worksheet),
+                        ExportValue::Text(text) => worksheet.write_string(*row, column, xlsx_text(text)),
+                        ExportValue::Integer(value) => worksheet.write_number(*row, column, *value as f64),
+                        ExportValue::Number(value) => worksheet.write_number(*row, column, *value),
+                        ExportValue::Timestamp(at) => ExcelDateTime::from_timestamp(at.unix_timestamp())
+                            .and_then(|at| worksheet.write_datetime_with_format(*row, column, &at, date_format)),
+                    }
+                    .map_err(|e| error(&e))?;
+                }
+                *row += 1;
+            }
+            Inner::Parquet { writer, pending } => {
+                pending.push(values);
+                if pending.len() >= PARQUET_ROW_GROUP {
+                    write_row_group(writer, &self.kinds, pending).map_err(|e| error(&e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Output written so far, once there's enough of it to be worth sending.
+    pub fn chunk(&mut self) -> Option<Bytes> {
+        (self.output.len() >= EXPORT_CHUNK_BYTES).then(|| self.output.take().into())
+    }
+
+    /// Completes the file and returns what's left of it. This may block on
+    /// disk.
+    pub fn finish(self) -> Result<Box<dyn Read + Send>, AppError> {
+        let format = self.format;
+        let error = |e: &dyn std::fmt::Display| export_error(format, e);
+
+        match self.inner {
+            Inner::Csv(mut writer) => writer.flush().map_err(|e| error(&e))?,
+            Inner::Ndjson { .. } => {}
+            Inner::Xlsx { mut workbook, .. } => {
+                let mut file = tempfile::tempfile().map_err(|e| error(&e))?;
+                workbook.save_to_writer(&mut file).map_err(|e| error(&e))?;
+                file.rewind().map_err(|e| error(&e))?;
+                return Ok(Box::new(file));
+            }
+            Inner::Parquet { mut writer, mut pending } => {
+                if !pending.is_empty() {
+                    write_row_group(&mut writer, &self.kinds, &mut pending).map_err(|e| error(&e))?;
+                }
+                writer.into_inner().map_err(|e| error(&e))?;
+            }
+        }
+
+        Ok(Box::new(io::Cursor::new(self.output.take())))
+    }
+}
+
+/// A response body that runs the form's responses through `writer` as they
+/// are read, so a large export is never held in memory whole. Anything that
+/// goes wrong once the body has started aborts it rather than ending it
+/// early, so a client can't mistake a failed export for a complete one.
+pub fn stream(pool: PgPool, form_id: Uuid, writer: ExportWriter) -> StreamBody<impl Stream<Item = io::Result<Bytes>>> {
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFERED_CHUNKS);
+
+    tokio::spawn(async move {
+        let result = match send_rows(&pool, form_id, writer, &tx).await {
+            Ok(Some(writer)) => {
+                let tx = tx.clone();
+                tokio::task::spawn_blocking(move || send_rest(writer, &tx))
+                    .await
+                    .unwrap_or(Err(AppError::InternalError))
+            }
+            // The client went away
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            tracing::error!("export of form {} failed: {}", form_id, e);
+            let _ = tx.send(Err(io::Error::other("export failed"))).await;
+        }
+    });
+
+    StreamBody::new(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+async fn send_rows(
+    pool: &PgPool,
+    form_id: Uuid,
+    mut writer: ExportWriter,
+    tx: &mpsc::Sender<io::Result<Bytes>>,
+) -> Result<Option<ExportWriter>, AppError> {
+    let mut rows = sqlx::query_as::<_, ExportRow>(EXPORT_ROWS)
+        .bind(form_id)
+        .bind(None::<Vec<Uuid>>)
+        .fetch(pool);
+
+    let mut done = false;
+    while !done {
+        let mut batch = Vec::with_capacity(EXPORT_WRITE_BATCH);
+        while batch.len() < EXPORT_WRITE_BATCH {
+            match rows.try_next().await.map_err(AppError::DatabaseError)? {
+                Some(row) => batch.push(row),
+                None => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+
+        writer = write_batch(writer, batch).await?;
+        if let Some(chunk) = writer.chunk() {
+            if tx.send(Ok(chunk)).await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(writer))
+}
+
+// Writes rows on the blocking pool, since writers can block: xlsx rows
+// spill to a temporary file and parquet row groups are compressed as they
+// fill
+async fn write_batch(mut writer: ExportWriter, batch: Vec<ExportRow>) -> Result<ExportWriter, AppError> {
+    tokio::task::spawn_blocking(move || {
+        batch.iter().try_for_each(|row| writer.write(row))?;
+        Ok(writer)
+    })
+    .await
+    .unwrap_or(Err(AppError::InternalError))
+}
+
+fn send_rest(writer: ExportWriter, tx: &mpsc::Sender<io::Result<Bytes>>) -> Result<(), AppError> {
+    let format = writer.format;
+    let mut rest = writer.finish()?;
+
+    loop {
+        let mut chunk = vec![0; EXPORT_CHUNK_BYTES];
+        let read = rest.read(&mut chunk).map_err(|e| export_error(format, &e))?;
+        if read == 0 {
+            return Ok(());
+        }
+        chunk.truncate(read);
+        if tx.blocking_send(Ok(chunk.into())).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// Excel refuses longer strings outright
+fn xlsx_text(text: &str) -> &str {
+    match text.char_indices().nth(XLSX_MAX_STRING) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+fn parquet_schema(headers: &[String], kinds: &[ColumnKind]) -> parquet::errors::Result<SchemaType> {
+    let fields = headers
+        .iter()
+        .zip(kinds)
+        .map(|(header, kind)| {
+            let (physical, logical) = match kind {
+                ColumnKind::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+                ColumnKind::Integer => (PhysicalType::INT64, None),
+                ColumnKind::Number => (PhysicalType::DOUBLE, None),
+                ColumnKind::Timestamp => (
+                    PhysicalType::INT64,
+                    Some(LogicalType::Timestamp {
+                        is_adjusted_to_u_t_c: true,
+                        unit: TimeUnit::MICROS(MicroSeconds {}),
+                    }),
+                ),
+            };
+            SchemaType::primitive_type_builder(header, physical)
+                .with_repetition(Repetition::OPTIONAL)
+                .with_logical_type(logical)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    SchemaType::group_type_builder("responses").with_fields(fields).build()
+}
+
+// Writes the buffered rows as one row group, column by column
+fn write_row_group(
+    writer: &mut SerializedFileWriter<Output>,
+    kinds: &[ColumnKind],
+    rows: &mut Vec<Vec<ExportValue>>,
+) -> parquet::errors::Result<()> {
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+
+    while let Some(mut column) = row_group.next_column()? {
+        // Empty cells are nulls: definition level 0 and no value
+        let levels: Vec<i16> = rows
+            .iter()
+            .map(|row| !matches!(row[index], ExportValue::Empty) as i16)
+            .collect();
+        let cells = rows.iter().map(|row| &row[index]);
+
+        match kinds[index] {
+            ColumnKind::Text => {
+                let values: Vec<ByteArray> = cells
+                    .filter_map(|value| match value {
+                        ExportValue::Text(text) => Some(ByteArray::from(text.as_str())),
+                        _ => None,
+                    })
+                    .collect();
+                column.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnKind::Integer => {
+                let values: Vec<i64> = cells
+                    .filter_map(|value| match value {
+                        ExportValue::Integer(value) => Some(*value),
+                        _ => None,
+                    })
+                    .collect();
+                column.typed::<Int64Type>().write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnKind::Number => {
+                let values: Vec<f64> = cells
+                    .filter_map(|value| match value {
+                        ExportValue::Number(value) => Some(*value),
+                        _ => None,
+                    })
+                    .collect();
+                column.typed::<DoubleType>().write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnKind::Timestamp => {
+                let values: Vec<i64> = cells
+                    .filter_map(|value| match value {
+                        ExportValue::Timestamp(at) => Some((at.unix_timestamp_nanos() / 1_000) as i64),
+                        _ => None,
+                    })
+                    .collect();
+                column.typed::<Int64Type>().write_batch(&values, Some(&levels), None)?;
+            }
+        }
+
+        column.close()?;
+        index += 1;
+    }
+
+    row_group.close()?;
+    rows.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn answers_become_one_cell_of_text() {
+        assert_eq!(answer_text(&JsonValue::Null), "");
+        assert_eq!(answer_text(&json!("Yes")), "Yes");
+        assert_eq!(answer_text(&json!(4.5)), "4.5");
+        assert_eq!(answer_text(&json!(true)), "true");
+        assert_eq!(answer_text(&json!(["Red", "Blue"])), "Red; Blue");
+        assert_eq!(answer_text(&json!([])), "");
+    }
+
+    #[test]
+    fn date_formats() {
+        let at = datetime!(2024-03-05 14:30:00 UTC);
+
+        assert_eq!(DateFormat::parse(None).unwrap().format(at), "2024-03-05T14:30:00Z");
+        assert_eq!(DateFormat::parse(Some("unix")).unwrap().format(at), "1709649000");
+        assert_eq!(
+            DateFormat::parse(Some("[day]/[month]/[year]")).unwrap().format(at),
+            "05/03/2024"
+        );
+        assert!(DateFormat::parse(Some("[nonsense]")).is_err());
+    }
+
+    #[test]
+    fn unix_timestamps_stay_numbers_in_json() {
+        let at = ExportValue::Timestamp(datetime!(2024-03-05 14:30:00 UTC));
+
+        assert_eq!(at.to_json(&DateFormat::Unix), json!(1709649000));
+        assert_eq!(at.to_json(&DateFormat::Rfc3339), json!("2024-03-05T14:30:00Z"));
+        assert_eq!(ExportValue::Empty.to_json(&DateFormat::Rfc3339), JsonValue::Null);
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use sqlx::types::JsonValue;
+use uuid::Uuid;
+
+use crate::{
+    elements::ElementType,
+    error::FieldError,
+    models::FormElement,
+    validation::{check_answer, is_answered, shown_elements},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionGrade {
+    pub element_id: Uuid,
+    pub correct: bool,
+    pub points_awarded: i32,
+    pub points_possible: i32,
+}
+
+/// Score breakdown stored with a quiz response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grade {
+    pub score: i32,
+    pub max_score: i32,
+    pub questions: Vec<QuestionGrade>,
+}
+
+// Free text and files need a human to mark them
+pub fn is_gradable(element_type: ElementType) -> bool {
+    !matches!(element_type, ElementType::LongText | ElementType::FileUpload)
+}
+
+/// Checks an element's answer key. Short text and email questions may list
+/// several accepted answers; everything else takes a single valid answer.
+pub fn validate_answer_key(
+    element_type: ElementType,
+    options: Option<&JsonValue>,
+    correct_answer: Option<&JsonValue>,
+    points: i32,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if points < 0 {
+        errors.push(FieldError::new("points", "must not be negative"));
+    }
+
+    let Some(answer) = correct_answer.filter(|answer| !answer.is_null()) else {
+        return errors;
+    };
+
+    if !is_gradable(element_type) {
+        errors.push(FieldError::new(
+            "correct_answer",
+            format!("{} questions cannot be auto-graded", element_type.as_str()),
+        ));
+        return errors;
+    }
+
+    let accepted = match (element_type, answer) {
+        (ElementType::ShortText | ElementType::Email, JsonValue::Array(answers)) if !answers.is_empty() => {
+            answers.iter().collect()
+        }
+        _ => vec![answer],
+    };
+    for answer in accepted {
+        if let Err(message) = check_answer(element_type, options, answer) {
+            errors.push(FieldError::new("correct_answer", message));
+            break;
+        }
+    }
+
+    errors
+}
+
+/// Grades validated answers against the answer keys of the questions the
+/// respondent was shown. Unanswered questions score nothing.
+pub fn grade_response(elements: &[FormElement], answers: &JsonValue) -> Grade {
+    let empty = Map::new();
+    let answers = answers.as_object().unwrap_or(&empty);
+
+    let questions: Vec<QuestionGrade> = shown_elements(elements, answers)
+        .into_iter()
+        .filter_map(|element| {
+            let key = element.correct_answer.as_ref().filter(|key| !key.is_null())?;
+            let correct = answers
+                .get(&element.id.to_string())
+                .filter(|answer| is_answered(answer))
+                .map(|answer| matches_key(element.element_type, answer, key))
+                .unwrap_or(false);
+
+            Some(QuestionGrade {
+                element_id: element.id,
+                correct,
+                points_awarded: if correct { element.points } else { 0 },
+                points_possible: element.points,
+            })
+        })
+        .collect();
+
+    Grade {
+        score: questions.iter().map(|q| q.points_awarded).sum(),
+        max_score: questions.iter().map(|q| q.points_possible).sum(),
+        questions,
+    }
+}
+
+fn matches_key(element_type: ElementType, answer: &JsonValue, key: &JsonValue) -> bool {
+    match element_type {
+        ElementType::ShortText | ElementType::Email => {
+            let normalise = |value: &JsonValue| value.as_str().map(|text| text.trim().to_lowercase());
+            let given = normalise(answer);
+            match key {
+                JsonValue::Array(keys) => keys.iter().any(|key| given.is_some() && normalise(key) == given),
+                key => given.is_some() && normalise(key) == given,
+            }
+        }
+        // Every correct choice and nothing else
+        ElementType::MultipleChoice => match (answer.as_array(), key.as_array()) {
+            (Some(given), Some(expected)) => {
+                given.len() == expected.len() && expected.iter().all(|choice| given.contains(choice))
+            }
+            _ => false,
+        },
+        ElementType::Number | ElementType::Rating | ElementType::LinearScale => {
+            match (answer.as_f64(), key.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+        }
+        _ => answer == key,
+    }
+}
+
+/// Removes answer keys before elements are shown to respondents.
+pub fn hide_answer_keys(elements: &mut [FormElement]) {
+    for element in elements {
+        element.correct_answer = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn keyed(element_type: ElementType, options: Option<JsonValue>, key: JsonValue, points: i32) -> FormElement {
+        FormElement {
+            correct_answer: Some(key),
+            points,
+            ..FormElement::for_test(element_type, options)
+        }
+    }
+
+    #[test]
+    fn text_answers_ignore_case_and_surrounding_space() {
+        let element = keyed(ElementType::ShortText, None, json!(["Paris", "paris, france"]), 2);
+        let answers = json!({ element.id.to_string(): "  PARIS " });
+
+        let grade = grade_response(&[element], &answers);
+        assert_eq!((grade.score, grade.max_score), (2, 2));
+    }
+
+    #[test]
+    fn multiple_choice_needs_exactly_the_right_choices() {
+        let options = json!({ "choices": ["A", "B", "C"] });
+        let element = keyed(ElementType::MultipleChoice, Some(options), json!(["A", "C"]), 1);
+        let key = element.id.to_string();
+        let elements = [element];
+
+        assert_eq!(grade_response(&elements, &json!({ &key: ["C", "A"] })).score, 1);
+        assert_eq!(grade_response(&elements, &json!({ &key: ["A"] })).score, 0);
+        assert_eq!(grade_response(&elements, &json!({ &key: ["A", "B", "C"] })).score, 0);
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        let element = keyed(ElementType::Number, None, json!(3), 1);
+        let answers = json!({ element.id.to_string(): 3.0 });
+
+        assert_eq!(grade_response(&[element], &answers).score, 1);
+    }
+
+    #[test]
+    fn unanswered_and_unkeyed_questions() {
+        let keyed = keyed(ElementType::ShortText, None, json!("yes"), 3);
+        let unkeyed = FormElement::for_test(ElementType::ShortText, None);
+        let answers = json!({ unkeyed.id.to_string(): "anything" });
+
+        let grade = grade_response(&[keyed, unkeyed], &answers);
+        assert_eq!((grade.score, grade.max_score), (0, 3));
+        assert_eq!(grade.questions.len(), 1);
+    }
+
+    #[test]
+    fn answer_keys_are_checked_like_answers() {
+        let options = json!({ "choices": ["A", "B"] });
+        assert!(validate_answer_key(ElementType::SingleChoice, Some(&options), Some(&json!("A")), 1).is_empty());
+
+        let errors = validate_answer_key(ElementType::SingleChoice, Some(&options), Some(&json!("Z")), -1);
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["points", "correct_answer"]);
+    }
+
+    #[test]
+    fn free_text_cannot_be_auto_graded() {
+        let errors = validate_answer_key(ElementType::LongText, None, Some(&json!("essay")), 1);
+        assert_eq!(errors.len(), 1);
+        assert!(validate_answer_key(ElementType::LongText, None, None, 1).is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::VecDeque, time::Duration};
+
+use futures::Stream;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgListener, PgConnection, PgPool};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::models::ResponseEvent;
+
+// Postgres channel announcing which form has new events
+const CHANNEL: &str = "response_events";
+// Announcements a slow subscriber may fall behind by before it has to
+// catch up from the table
+const NOTICE_BUFFER: usize = 1024;
+const REPLAY_BATCH: i64 = 500;
+// How long events are kept for reconnecting clients
+const RETENTION_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// How long a ticket may wait before it's used to open a feed
+const TICKET_SECONDS: f64 = 60.0;
+
+/// Records an event for the form's live feeds. Call inside the transaction
+/// making the change, as its last statement: listeners hear of it when it
+/// commits, and the form's events commit in id order.
+pub async fn publish(conn: &mut PgConnection, form_id: Uuid, event: &str, data: JsonValue) -> Result<(), sqlx::Error> {
+    // Held until commit, so a client that has seen an event never misses
+    // an earlier one that committed after it
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))")
+        .bind(form_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("INSERT INTO response_events (form_id, event, data) VALUES ($1, $2, $3)")
+        .bind(form_id)
+        .bind(event)
+        .bind(data)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(form_id.to_string())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// A new single-use ticket for opening the form's live feed as `user_id`.
+/// Only its hash is stored.
+pub async fn issue_ticket(pool: &PgPool, form_id: Uuid, user_id: Uuid) -> Result<(String, OffsetDateTime), sqlx::Error> {
+    let ticket = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let expires_at = sqlx::query_scalar(
+        "INSERT INTO live_tickets (ticket_hash, form_id, user_id, expires_at)
+         VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+         RETURNING expires_at"
+    )
+    .bind(ticket_hash(&ticket))
+    .bind(form_id)
+    .bind(user_id)
+    .bind(TICKET_SECONDS)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((ticket, expires_at))
+}
+
+/// The user a ticket for the form was issued to, if it's unused and
+/// unexpired. The ticket can't be used again.
+pub async fn redeem_ticket(pool: &PgPool, form_id: Uuid, ticket: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "DELETE FROM live_tickets WHERE ticket_hash = $1 AND form_id = $2 AND expires_at > NOW() RETURNING user_id"
+    )
+    .bind(ticket_hash(ticket))
+    .bind(form_id)
+    .fetch_optional(pool)
+    .await
+}
+
+fn ticket_hash(ticket: &str) -> String {
+    hex::encode(Sha256::digest(ticket.as_bytes()))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Notice {
+    Form(Uuid),
+    // The listener reconnected and may have missed announcements
+    Resync,
+}
+
+/// Tells live feeds on this server about events published by any server.
+#[derive(Clone)]
+pub struct LiveFeed {
+    notices: broadcast::Sender<Notice>,
+}
+
+/// Listens for published events, and prunes old ones and expired tickets,
+/// for as long as the server runs.
+pub async fn spawn_listener(pool: PgPool) -> anyhow::Result<LiveFeed> {
+    let mut listener = PgListener::connect_with(&pool).await?;
+    listener.listen(CHANNEL).await?;
+
+    let (notices, _) = broadcast::channel(NOTICE_BUFFER);
+    let sender = notices.clone();
+    tokio::spawn(async move {
+        loop {
+            // No subscribers is fine; the send errors are ignored
+            match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    if let Ok(form_id) = notification.payload().parse() {
+                        let _ = sender.send(Notice::Form(form_id));
+                    }
+                }
+                Ok(None) => {
+                    tracing::warn!("live feed listener lost its connection; reconnecting");
+                    let _ = sender.send(Notice::Resync);
+                }
+                Err(e) => {
+                    tracing::error!("live feed listener failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let pruned = sqlx::query("DELETE FROM response_events WHERE created_at < NOW() - make_interval(secs => $1)")
+                .bind(RETENTION_SECONDS)
+                .execute(&pool)
+                .await;
+            if let Err(e) = pruned {
+                tracing::error!("pruning response events failed: {}", e);
+            }
+            let expired = sqlx::query("DELETE FROM live_tickets WHERE expires_at <= NOW()")
+                .execute(&pool)
+                .await;
+            if let Err(e) = expired {
+                tracing::error!("deleting expired live tickets failed: {}", e);
+            }
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+
+    Ok(LiveFeed { notices })
+}
+
+impl LiveFeed {
+    /// The form's events after `after`, oldest first, then new ones as they
+    /// are published. Without `after` only new events are sent. If the event
+    /// `after` names has been pruned, some after it may have been too, so a
+    /// `reset` event is sent first and the feed goes on from the latest one.
+    pub async fn subscribe(&self, pool: PgPool, form_id: Uuid, after: Option<i64>) -> Result<Subscription, sqlx::Error> {
+        // Subscribed before looking for the latest event, so nothing
+        // published in between is missed
+        let notices = self.notices.subscribe();
+        let retained = match after {
+            Some(id) => {
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM response_events WHERE id = $1 AND form_id = $2)")
+                    .bind(id)
+                    .bind(form_id)
+                    .fetch_one(&pool)
+                    .await?
+            }
+            None => false,
+        };
+
+        let mut pending = VecDeque::new();
+        let last_id = match after {
+            Some(id) if retained => id,
+            _ => {
+                let latest = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM response_events WHERE form_id = $1")
+                    .bind(form_id)
+                    .fetch_one(&pool)
+                    .await?;
+                if after.is_some() {
+                    pending.push_back(reset_event(form_id, latest));
+                }
+                latest
+            }
+        };
+
+        Ok(Subscription {
+            pool,
+            form_id,
+            notices,
+            last_id,
+            pending,
+            stale: retained,
+        })
+    }
+}
+
+// Tells a resuming client its missed events are gone and it should reload
+// the responses. Its id is the latest event's, to resume from next time.
+fn reset_event(form_id: Uuid, latest: i64) -> ResponseEvent {
+    ResponseEvent {
+        id: latest,
+        form_id,
+        event: "reset".to_string(),
+        data: json!({ "reason": "events after last_event_id are no longer available" }),
+        created_at: OffsetDateTime::now_utc(),
+    }
+}
+
+pub struct Subscription {
+    pool: PgPool,
+    form_id: Uuid,
+    notices: broadcast::Receiver<Notice>,
+    last_id: i64,
+    pending: VecDeque<ResponseEvent>,
+    // There may be events after `last_id` we haven't fetched
+    stale: bool,
+}
+
+impl Subscription {
+    /// The next event; `None` once the feed can't go on.
+    pub async fn next(&mut self) -> Option<ResponseEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.last_id = event.id;
+                return Some(event);
+            }
+
+            if self.stale {
+                let events = sqlx::query_as::<_, ResponseEvent>(
+                    "SELECT * FROM response_events WHERE form_id = $1 AND id > $2 ORDER BY id LIMIT $3"
+                )
+                .bind(self.form_id)
+                .bind(self.last_id)
+                .bind(REPLAY_BATCH)
+                .fetch_all(&self.pool)
+                .await;
+                match events {
+                    Ok(events) => {
+                        self.stale = events.len() as i64 == REPLAY_BATCH;
+                        self.pending = events.into();
+                    }
+                    Err(e) => {
+                        tracing::error!("live feed for form {} failed: {}", self.form_id, e);
+                        return None;
+                    }
+                }
+                continue;
+            }
+
+            match self.notices.recv().await {
+                Ok(Notice::Form(form_id)) => self.stale = form_id == self.form_id,
+                Ok(Notice::Resync) | Err(RecvError::Lagged(_)) => self.stale = true,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = ResponseEvent> {
+        futures::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|event| (event, subscription))
+        })
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use sqlx::types::JsonValue;
+use uuid::Uuid;
+
+use crate::{
+    error::FieldError,
+    models::{FormElement, FormSection},
+    validation::is_answered,
+};
+
+/// Show/hide and skip-to rules attached to a form element.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElementLogic {
+    // The element is only shown when this holds
+    pub show_if: Option<Condition>,
+    // Checked in order once the element has been shown; the first match
+    // jumps to its target and hides everything in between
+    #[serde(default)]
+    pub skip_to: Vec<SkipRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SkipRule {
+    pub when: Condition,
+    // None skips to the end of the form
+    pub target_element_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Answer(AnswerCondition),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerCondition {
+    pub element_id: Uuid,
+    pub operator: Operator,
+    #[serde(default)]
+    pub value: JsonValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Equals,
+    NotEquals,
+    Contains,
+    NotContains,
+    GreaterThan,
+    LessThan,
+    IsAnswered,
+    IsNotAnswered,
+}
+
+impl ElementLogic {
+    /// Points references at new element ids, e.g. when a form is copied.
+    /// Ids missing from `ids` are left alone.
+    pub fn remap(&mut self, ids: &HashMap<Uuid, Uuid>) {
+        if let Some(condition) = &mut self.show_if {
+            condition.remap(ids);
+        }
+        for rule in &mut self.skip_to {
+            rule.when.remap(ids);
+            if let Some(target) = rule.target_element_id.and_then(|target| ids.get(&target)) {
+                rule.target_element_id = Some(*target);
+            }
+        }
+    }
+}
+
+impl Condition {
+    pub fn evaluate(&self, answers: &Map<String, JsonValue>) -> bool {
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(answers)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(answers)),
+            Condition::Answer(condition) => condition.evaluate(answers),
+        }
+    }
+
+    fn remap(&mut self, ids: &HashMap<Uuid, Uuid>) {
+        match self {
+            Condition::All(conditions) | Condition::Any(conditions) => {
+                conditions.iter_mut().for_each(|c| c.remap(ids))
+            }
+            Condition::Answer(condition) => {
+                if let Some(id) = ids.get(&condition.element_id) {
+                    condition.element_id = *id;
+                }
+            }
+        }
+    }
+
+    fn element_ids(&self, ids: &mut Vec<Uuid>) {
+        match self {
+            Condition::All(conditions) | Condition::Any(conditions) => {
+                conditions.iter().for_each(|c| c.element_ids(ids))
+            }
+            Condition::Answer(condition) => ids.push(condition.element_id),
+        }
+    }
+}
+
+impl AnswerCondition {
+    fn evaluate(&self, answers: &Map<String, JsonValue>) -> bool {
+        let answer = answers
+            .get(&self.element_id.to_string())
+            .filter(|value| is_answered(value));
+
+        match (self.operator, answer) {
+            (Operator::IsAnswered, answer) => answer.is_some(),
+            (Operator::IsNotAnswered, answer) => answer.is_none(),
+            (Operator::NotEquals, None) | (Operator::NotContains, None) => true,
+            (_, None) => false,
+            (Operator::Equals, Some(answer)) => values_equal(answer, &self.value),
+            (Operator::NotEquals, Some(answer)) => !values_equal(answer, &self.value),
+            (Operator::Contains, Some(answer)) => contains(answer, &self.value),
+            (Operator::NotContains, Some(answer)) => !contains(answer, &self.value),
+            (Operator::GreaterThan, Some(answer)) => compare(answer, &self.value) == Some(std::cmp::Ordering::Greater),
+            (Operator::LessThan, Some(answer)) => compare(answer, &self.value) == Some(std::cmp::Ordering::Less),
+        }
+    }
+}
+
+fn values_equal(answer: &JsonValue, value: &JsonValue) -> bool {
+    match (answer.as_f64(), value.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => answer == value,
+    }
+}
+
+fn contains(answer: &JsonValue, value: &JsonValue) -> bool {
+    match answer {
+        JsonValue::Array(items) => items.iter().any(|item| values_equal(item, value)),
+        JsonValue::String(text) => value.as_str().map(|v| text.contains(v)).unwrap_or(false),
+        _ => false,
+    }
+}
+
+// Dates and times are stored as zero-padded strings, so comparing them as
+// strings orders them correctly
+fn compare(answer: &JsonValue, value: &JsonValue) -> Option<std::cmp::Ordering> {
+    match (answer, value) {
+        (JsonValue::String(a), JsonValue::String(b)) => Some(a.cmp(b)),
+        _ => answer.as_f64()?.partial_cmp(&value.as_f64()?),
+    }
+}
+
+/// Works out which elements a respondent sees given their answers. `elements`
+/// must be in form order; conditions only ever look at answers to elements
+/// that are themselves visible. A skip whose target is missing or doesn't
+/// come later is ignored rather than hiding the rest of the form.
+pub fn visible_elements(elements: &[FormElement], answers: &Map<String, JsonValue>) -> HashSet<Uuid> {
+    let positions: HashMap<Uuid, usize> = elements
+        .iter()
+        .enumerate()
+        .map(|(position, element)| (element.id, position))
+        .collect();
+    let mut visible = HashSet::new();
+    let mut visible_answers = Map::new();
+    let mut skipping_to: Option<Option<Uuid>> = None;
+
+    for (position, element) in elements.iter().enumerate() {
+        if let Some(target) = skipping_to {
+            if target != Some(element.id) {
+                continue;
+            }
+            skipping_to = None;
+        }
+
+        let logic = element.logic.as_ref().map(|logic| &logic.0);
+
+        if let Some(condition) = logic.and_then(|logic| logic.show_if.as_ref()) {
+            if !condition.evaluate(&visible_answers) {
+                continue;
+            }
+        }
+
+        visible.insert(element.id);
+        let key = element.id.to_string();
+        if let Some(answer) = answers.get(&key) {
+            visible_answers.insert(key, answer.clone());
+        }
+
+        if let Some(logic) = logic {
+            let reachable = |rule: &&SkipRule| match rule.target_element_id {
+                Some(target) => positions.get(&target).is_some_and(|&target| target > position),
+                None => true,
+            };
+            if let Some(rule) = logic
+                .skip_to
+                .iter()
+                .filter(reachable)
+                .find(|rule| rule.when.evaluate(&visible_answers))
+            {
+                skipping_to = Some(rule.target_element_id);
+            }
+        }
+    }
+
+    visible
+}
+
+/// Where an element sits in the form: its section's position (elements
+/// without a section come first), then its position within the section.
+pub fn element_position(section_id: Option<Uuid>, order_index: i32, sections: &[FormSection]) -> (Option<i32>, i32) {
+    let section_order = section_id
+        .and_then(|id| sections.iter().find(|section| section.id == id))
+        .map(|section| section.order_index);
+
+    (section_order, order_index)
+}
+
+/// Checks every element's logic against the layout as a whole, for edits that
+/// move or remove elements other than the one being changed. Fields are
+/// prefixed with `elements[<id>].`.
+pub fn validate_layout(elements: &[FormElement], sections: &[FormSection]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    for element in elements {
+        let Some(logic) = &element.logic else {
+            continue;
+        };
+        let siblings: Vec<FormElement> = elements
+            .iter()
+            .filter(|sibling| sibling.id != element.id)
+            .cloned()
+            .collect();
+        let position = element_position(element.section_id, element.order_index, sections);
+
+        errors.extend(
+            validate_logic(logic, position, &siblings, sections)
+                .into_iter()
+                .map(|error| FieldError::new(format!("elements[{}].{}", element.id, error.field), error.message)),
+        );
+    }
+
+    errors
+}
+
+/// Checks that conditions only reference earlier elements of the same form and
+/// that skip targets come later. `siblings` are the form's other elements.
+pub fn validate_logic(
+    logic: &ElementLogic,
+    position: (Option<i32>, i32),
+    siblings: &[FormElement],
+    sections: &[FormSection],
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let find = |id: Uuid| {
+        siblings
+            .iter()
+            .find(|element| element.id == id)
+            .map(|element| element_position(element.section_id, element.order_index, sections))
+    };
+
+    let check_condition = |errors: &mut Vec<FieldError>, field: &str, condition: &Condition| {
+        let mut ids = Vec::new();
+        condition.element_ids(&mut ids);
+        for id in ids {
+            match find(id) {
+                None => errors.push(FieldError::new(field, format!("element {} is not part of this form", id))),
+                Some(other) if other >= position => errors.push(FieldError::new(
+                    field,
+                    format!("element {} must come before this element", id),
+                )),
+                Some(_) => {}
+            }
+        }
+    };
+
+    if let Some(condition) = &logic.show_if {
+        check_condition(&mut errors, "logic.show_if", condition);
+    }
+
+    for (i, rule) in logic.skip_to.iter().enumerate() {
+        check_condition(&mut errors, &format!("logic.skip_to[{}].when", i), &rule.when);
+
+        if let Some(target) = rule.target_element_id {
+            let field = format!("logic.skip_to[{}].target_element_id", i);
+            match find(target) {
+                None => errors.push(FieldError::new(field, "target is not part of this form")),
+                Some(other) if other <= position => {
+                    errors.push(FieldError::new(field, "target must come after this element"))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::elements::ElementType;
+
+    fn element(order_index: i32) -> FormElement {
+        FormElement {
+            order_index,
+            ..FormElement::for_test(ElementType::ShortText, None)
+        }
+    }
+
+    fn skip_when_answered(source: Uuid, target: Option<Uuid>) -> ElementLogic {
+        ElementLogic {
+            show_if: None,
+            skip_to: vec![SkipRule {
+                when: Condition::Answer(AnswerCondition {
+                    element_id: source,
+                    operator: Operator::IsAnswered,
+                    value: JsonValue::Null,
+                }),
+                target_element_id: target,
+            }],
+        }
+    }
+
+    fn form_with_skip(target: impl Fn(&[FormElement]) -> Option<Uuid>) -> (Vec<FormElement>, Map<String, JsonValue>) {
+        let mut elements: Vec<FormElement> = (0..4).map(element).collect();
+        let source = elements[1].id;
+        let logic = skip_when_answered(source, target(&elements));
+        elements[1].logic = Some(Json(logic));
+
+        let mut answers = Map::new();
+        answers.insert(source.to_string(), json!("yes"));
+        (elements, answers)
+    }
+
+    #[test]
+    fn skip_hides_elements_up_to_target() {
+        let (elements, answers) = form_with_skip(|elements| Some(elements[3].id));
+        let visible = visible_elements(&elements, &answers);
+
+        assert!(visible.contains(&elements[1].id));
+        assert!(!visible.contains(&elements[2].id));
+        assert!(visible.contains(&elements[3].id));
+    }
+
+    #[test]
+    fn skip_to_end_hides_everything_after() {
+        let (elements, answers) = form_with_skip(|_| None);
+        let visible = visible_elements(&elements, &answers);
+
+        assert_eq!(visible.len(), 2);
+    }
+
+    #[test]
+    fn missing_skip_target_is_ignored() {
+        let (elements, answers) = form_with_skip(|_| Some(Uuid::new_v4()));
+        let visible = visible_elements(&elements, &answers);
+
+        assert_eq!(visible.len(), elements.len());
+    }
+
+    #[test]
+    fn backward_skip_target_is_ignored() {
+        let (elements, answers) = form_with_skip(|elements| Some(elements[0].id));
+        let visible = visible_elements(&elements, &answers);
+
+        assert_eq!(visible.len(), elements.len());
+    }
+
+    #[test]
+    fn show_if_only_sees_visible_answers() {
+        let mut elements: Vec<FormElement> = (0..3).map(element).collect();
+        let hidden = elements[0].id;
+        elements[0].logic = Some(Json(ElementLogic {
+            show_if: Some(Condition::Answer(AnswerCondition {
+                element_id: elements[1].id,
+                operator: Operator::IsAnswered,
+                value: JsonValue::Null,
+            })),
+            skip_to: vec![],
+        }));
+        elements[2].logic = Some(Json(ElementLogic {
+            show_if: Some(Condition::Answer(AnswerCondition {
+                element_id: hidden,
+                operator: Operator::IsAnswered,
+                value: JsonValue::Null,
+            })),
+            skip_to: vec![],
+        }));
+
+        let mut answers = Map::new();
+        answers.insert(hidden.to_string(), json!("stale"));
+        let visible = visible_elements(&elements, &answers);
+
+        assert!(!visible.contains(&hidden));
+        assert!(!visible.contains(&elements[2].id));
+    }
+}
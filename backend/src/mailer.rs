@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::Config;
+
+// Attempts before an email is marked failed, and the wait before the first
+// retry; each later retry waits twice as long as the one before
+const MAX_ATTEMPTS: i32 = 6;
+const RETRY_BASE: Duration = Duration::from_secs(60);
+const RETRY_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+// How long a claimed email is hidden from other workers
+const CLAIM_LEASE_SECONDS: f64 = 120.0;
+const CLAIM_BATCH: i64 = 20;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Emails queued in any hour, so a form can't be used to flood an inbox
+const MAX_PER_ADDRESS_PER_HOUR: i64 = 20;
+const MAX_PER_FORM_PER_HOUR: i64 = 500;
+
+/// Sends plain text email through the SMTP server in `SMTP_URL`.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&config.smtp_url)?
+            .timeout(Some(SMTP_TIMEOUT))
+            .build();
+
+        Ok(Self {
+            transport,
+            from: config.mail_from.parse()?,
+        })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().map_err(|e| MailError::Invalid(format!("bad recipient {:?}: {}", to, e)))?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())
+            .map_err(|e| MailError::Invalid(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| MailError::Smtp(e.to_string()))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    // Retrying can't help
+    #[error("invalid email: {0}")]
+    Invalid(String),
+
+    #[error("SMTP error: {0}")]
+    Smtp(String),
+}
+
+/// Adds an email to the outbox. Call inside the transaction that causes it,
+/// so it's sent exactly when the change commits. Past the hourly limits for
+/// its recipient or form the email is dropped, with a warning.
+pub async fn queue(
+    conn: &mut PgConnection,
+    form_id: Option<Uuid>,
+    to: &str,
+    subject: &str,
+    body: &str,
+) -> Result<(), sqlx::Error> {
+    let (to_address, to_form): (i64, i64) = sqlx::query_as(
+        "SELECT
+             COUNT(*) FILTER (WHERE lower(to_address) = lower($1)),
+             COUNT(*) FILTER (WHERE form_id = $2)
+         FROM email_outbox
+         WHERE created_at > NOW() - INTERVAL '1 hour'
+         AND (lower(to_address) = lower($1) OR form_id = $2)"
+    )
+    .bind(to)
+    .bind(form_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if to_address >= MAX_PER_ADDRESS_PER_HOUR || to_form >= MAX_PER_FORM_PER_HOUR {
+        tracing::warn!("not queueing email to {} for form {:?}: hourly limit reached", to, form_id);
+        return Ok(());
+    }
+
+    sqlx::query(
+        "INSERT INTO email_outbox (form_id, to_address, subject, body)
+         VALUES ($1, $2, $3, $4)"
+    )
+    .bind(form_id)
+    .bind(to)
+    .bind(subject)
+    .bind(body)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Sends queued email in the background for as long as the server runs.
+pub fn spawn_worker(pool: PgPool, mailer: Mailer) {
+    tokio::spawn(async move {
+        loop {
+            match send_due(&pool, &mailer).await {
+                // A full batch suggests there's more waiting
+                Ok(sent) if sent as i64 == CLAIM_BATCH => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("sending queued email failed: {}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+#[derive(sqlx::FromRow)]
+struct DueEmail {
+    id: Uuid,
+    to_address: String,
+    subject: String,
+    body: String,
+    attempts: i32,
+}
+
+async fn send_due(pool: &PgPool, mailer: &Mailer) -> Result<usize, sqlx::Error> {
+    // SKIP LOCKED and the lease let several servers share the outbox
+    let due = sqlx::query_as::<_, DueEmail>(
+        "WITH due AS (
+             SELECT id FROM email_outbox
+             WHERE status = 'pending' AND next_attempt_at <= NOW()
+             ORDER BY next_attempt_at
+             LIMIT $1
+             FOR UPDATE SKIP LOCKED
+         )
+         UPDATE email_outbox e
+         SET next_attempt_at = NOW() + make_interval(secs => $2)
+         FROM due
+         WHERE e.id = due.id
+         RETURNING e.id, e.to_address, e.subject, e.body, e.attempts"
+    )
+    .bind(CLAIM_BATCH)
+    .bind(CLAIM_LEASE_SECONDS)
+    .fetch_all(pool)
+    .await?;
+
+    let sent = due.len();
+    for email in due {
+        let result = mailer.send(&email.to_address, &email.subject, &email.body).await;
+        let attempts = email.attempts + 1;
+        let status = match &result {
+            Ok(()) => "sent",
+            Err(MailError::Invalid(_)) => "failed",
+            Err(_) if attempts >= MAX_ATTEMPTS => "failed",
+            Err(_) => "pending",
+        };
+        if let Err(e) = &result {
+            tracing::warn!("email {} to {} failed: {}", email.id, email.to_address, e);
+        }
+
+        sqlx::query(
+            "UPDATE email_outbox
+             SET status = $2, attempts = $3, next_attempt_at = NOW() + make_interval(secs => $4),
+                 last_error = $5, sent_at = CASE WHEN $2 = 'sent' THEN NOW() END
+             WHERE id = $1"
+        )
+        .bind(email.id)
+        .bind(status)
+        .bind(attempts)
+        .bind(retry_delay(attempts).as_secs_f64())
+        .bind(result.err().map(|e| e.to_string()))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(sent)
+}
+
+fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.clamp(1, 16) as u32 - 1;
+    RETRY_BASE.saturating_mul(1 << doublings).min(RETRY_MAX)
+}
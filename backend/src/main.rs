@@ -1,20 +1,33 @@
 mod routes;
 mod models;
-mod handlers;
 mod db;
+mod definitions;
+mod export;
 mod config;
 mod error;
 mod auth;
+mod client;
+mod elements;
+mod grading;
+mod logic;
+mod validation;
+mod versions;
+mod storage;
+mod sheets;
+mod webhooks;
+mod mailer;
+mod notifications;
+mod live;
+mod drafts;
+mod uploads;
 
 use axum::{
-    routing::{get, post},
     Router,
     Extension,
 };
 use std::net::SocketAddr;
 use tower_http::cors::{CorsLayer, Any};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use sqlx::postgres::PgPoolOptions;
 use dotenv::dotenv;
 
 #[tokio::main]
@@ -30,12 +43,35 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let config = config::Config::from_env();
+
     // Database connection
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
+    let pool = db::create_pool(&config.database_url).await?;
+
+    // Uploaded files
+    let storage = storage::storage_from_env()?;
+
+    // Linked spreadsheets
+    let sheets = sheets::sheets_from_env()?;
+
+    // Sends queued webhook deliveries, and announces forms that close on
+    // their own
+    webhooks::spawn_worker(pool.clone())?;
+    webhooks::spawn_close_watch(pool.clone());
+
+    // Sends queued email, and owners' digests of new responses
+    let mailer = mailer::Mailer::from_config(&config)?;
+    mailer::spawn_worker(pool.clone(), mailer);
+    notifications::spawn_digests(pool.clone());
+
+    // Pushes response activity to live feeds, whichever server it happened on
+    let live_feed = live::spawn_listener(pool.clone()).await?;
+
+    // Deletes drafts nobody came back to
+    drafts::spawn_cleanup(pool.clone());
+
+    // Deletes uploads no response or draft ended up using
+    uploads::spawn_cleanup(pool.clone(), storage.clone());
 
     // Setup CORS
     let cors = CorsLayer::new()
@@ -47,16 +83,33 @@ async fn main() -> anyhow::Result<()> {
     let app = Router::new()
         .merge(routes::auth::router())
         .merge(routes::forms::router())
+        .merge(routes::templates::router())
+        .merge(routes::definitions::router())
+        .merge(routes::sections::router())
+        .merge(routes::versions::router())
         .merge(routes::responses::router())
+        .merge(routes::drafts::router())
+        .merge(routes::quiz::router())
+        .merge(routes::analytics::router())
+        .merge(routes::sheets::router())
+        .merge(routes::webhooks::router())
+        .merge(routes::notifications::router())
+        .merge(routes::live::router())
+        .merge(routes::shared::router())
+        .merge(routes::uploads::router())
         .merge(routes::payments::router())
         .layer(Extension(pool))
+        .layer(Extension(storage))
+        .layer(Extension(sheets))
+        .layer(Extension(live_feed))
+        .layer(Extension(client::TrustedProxies(config.trusted_proxies)))
         .layer(cors);
 
     // Run our application
     let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
     tracing::info!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
-use sqlx::types::JsonValue;
+use sqlx::types::{Json, JsonValue};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+use crate::{definitions::FormDefinition, elements::ElementType, grading::Grade, logic::ElementLogic};
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -15,13 +17,6 @@ pub struct User {
     pub updated_at: OffsetDateTime,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateUser {
-    pub email: String,
-    pub password: String,
-    pub full_name: Option<String>,
-}
-
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Form {
     pub id: Uuid,
@@ -30,8 +25,96 @@ pub struct Form {
     pub description: Option<String>,
     pub is_public: bool,
     pub allow_anonymous: bool,
+    #[sqlx(try_from = "String")]
+    pub status: FormStatus,
+    pub opens_at: Option<OffsetDateTime>,
+    pub closes_at: Option<OffsetDateTime>,
+    pub closed_message: Option<String>,
+    pub max_responses: Option<i32>,
+    pub max_responses_per_respondent: Option<i32>,
+    pub max_responses_per_client: Option<i32>,
+    pub is_quiz: bool,
+    pub show_score_immediately: bool,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
+    // Only filled in by queries that count responses against `max_responses`
+    #[sqlx(default)]
+    pub remaining_responses: Option<i64>,
+}
+
+impl Form {
+    /// Why the form cannot take a response at `now`, if it cannot.
+    pub fn closed_reason(&self, now: OffsetDateTime) -> Option<String> {
+        let closed_message = || {
+            self.closed_message
+                .clone()
+                .unwrap_or_else(|| "This form is no longer accepting responses".to_string())
+        };
+
+        match self.status {
+            FormStatus::Published => {}
+            FormStatus::Draft => return Some("This form has not been published yet".to_string()),
+            FormStatus::Closed | FormStatus::Archived => return Some(closed_message()),
+        }
+
+        if self.opens_at.map(|opens_at| now < opens_at).unwrap_or(false) {
+            return Some("This form is not open for responses yet".to_string());
+        }
+        if self.closes_at.map(|closes_at| now >= closes_at).unwrap_or(false) {
+            return Some(closed_message());
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormStatus {
+    Draft,
+    Published,
+    Closed,
+    Archived,
+}
+
+impl FormStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FormStatus::Draft => "draft",
+            FormStatus::Published => "published",
+            FormStatus::Closed => "closed",
+            FormStatus::Archived => "archived",
+        }
+    }
+
+    pub fn can_transition_to(&self, next: FormStatus) -> bool {
+        use FormStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Published)
+                | (Published, Closed)
+                | (Closed, Published)
+                | (Draft, Archived)
+                | (Published, Archived)
+                | (Closed, Archived)
+                | (Archived, Draft)
+        )
+    }
+}
+
+impl TryFrom<String> for FormStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "draft" => Ok(FormStatus::Draft),
+            "published" => Ok(FormStatus::Published),
+            "closed" => Ok(FormStatus::Closed),
+            "archived" => Ok(FormStatus::Archived),
+            _ => Err(format!("unknown form status `{}`", value)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,28 +123,229 @@ pub struct CreateForm {
     pub description: Option<String>,
     pub is_public: bool,
     pub allow_anonymous: bool,
+    #[serde(default)]
+    pub opens_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub closes_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub closed_message: Option<String>,
+    #[serde(default)]
+    pub max_responses: Option<i32>,
+    #[serde(default)]
+    pub max_responses_per_respondent: Option<i32>,
+    #[serde(default)]
+    pub max_responses_per_client: Option<i32>,
+    #[serde(default)]
+    pub is_quiz: bool,
+    #[serde(default)]
+    pub show_score_immediately: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FormSection {
+    pub id: Uuid,
+    pub form_id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+    pub order_index: i32,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateFormSection {
+    pub title: String,
+    pub description: Option<String>,
+    pub order_index: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct FormElement {
     pub id: Uuid,
     pub form_id: Uuid,
-    pub element_type: String,
+    pub section_id: Option<Uuid>,
+    #[sqlx(try_from = "String")]
+    pub element_type: ElementType,
     pub question: String,
     pub required: bool,
     pub options: Option<JsonValue>,
     pub order_index: i32,
+    pub logic: Option<Json<ElementLogic>>,
+    // Original type of rows that predate typed elements and could not be mapped
+    pub legacy_element_type: Option<String>,
+    // Original options of such rows that did not fit their type's schema
+    #[serde(default)]
+    pub legacy_options: Option<JsonValue>,
+    // Answer key for quizzes; never sent to respondents
+    #[serde(default)]
+    pub correct_answer: Option<JsonValue>,
+    #[serde(default = "default_points")]
+    pub points: i32,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateFormElement {
-    pub element_type: String,
+    #[serde(default)]
+    pub section_id: Option<Uuid>,
+    pub element_type: ElementType,
     pub question: String,
     pub required: bool,
     pub options: Option<JsonValue>,
     pub order_index: i32,
+    #[serde(default)]
+    pub logic: Option<ElementLogic>,
+    #[serde(default)]
+    pub correct_answer: Option<JsonValue>,
+    #[serde(default = "default_points")]
+    pub points: i32,
+}
+
+fn default_points() -> i32 {
+    1
+}
+
+impl From<FormElement> for CreateFormElement {
+    fn from(element: FormElement) -> Self {
+        Self {
+            section_id: element.section_id,
+            element_type: element.element_type,
+            question: element.question,
+            required: element.required,
+            options: element.options,
+            order_index: element.order_index,
+            logic: element.logic.map(|logic| logic.0),
+            correct_answer: element.correct_answer,
+            points: element.points,
+        }
+    }
+}
+
+#[cfg(test)]
+impl FormElement {
+    /// An optional, ungraded element of a throwaway form, for unit tests.
+    pub fn for_test(element_type: ElementType, options: Option<JsonValue>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            form_id: Uuid::nil(),
+            section_id: None,
+            element_type,
+            question: element_type.as_str().to_string(),
+            required: false,
+            options,
+            order_index: 0,
+            logic: None,
+            legacy_element_type: None,
+            legacy_options: None,
+            correct_answer: None,
+            points: default_points(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+}
+
+/// What respondents see of a form: nothing that identifies its owner or
+/// gives away its limits.
+#[derive(Debug, Serialize)]
+pub struct RespondentForm {
+    pub title: String,
+    pub description: Option<String>,
+    pub status: FormStatus,
+    pub closes_at: Option<OffsetDateTime>,
+    pub allow_anonymous: bool,
+    pub is_quiz: bool,
+    pub show_score_immediately: bool,
+}
+
+impl From<Form> for RespondentForm {
+    fn from(form: Form) -> Self {
+        Self {
+            title: form.title,
+            description: form.description,
+            status: form.status,
+            closes_at: form.closes_at,
+            allow_anonymous: form.allow_anonymous,
+            is_quiz: form.is_quiz,
+            show_score_immediately: form.show_score_immediately,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RespondentSection {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+impl From<FormSection> for RespondentSection {
+    fn from(section: FormSection) -> Self {
+        Self {
+            id: section.id,
+            title: section.title,
+            description: section.description,
+        }
+    }
+}
+
+/// A question as respondents see it, without its answer key.
+#[derive(Debug, Serialize)]
+pub struct RespondentElement {
+    pub id: Uuid,
+    pub section_id: Option<Uuid>,
+    pub element_type: ElementType,
+    pub question: String,
+    pub required: bool,
+    pub options: Option<JsonValue>,
+    pub logic: Option<Json<ElementLogic>>,
+    pub points: i32,
+}
+
+impl From<FormElement> for RespondentElement {
+    fn from(element: FormElement) -> Self {
+        Self {
+            id: element.id,
+            section_id: element.section_id,
+            element_type: element.element_type,
+            question: element.question,
+            required: element.required,
+            options: element.options,
+            logic: element.logic,
+            points: element.points,
+        }
+    }
+}
+
+/// One step of a batch edit. `order_index` is the position to put the element
+/// at within its section; everything is renumbered once the batch is applied.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ElementOperation {
+    Create {
+        #[serde(flatten)]
+        element: CreateFormElement,
+    },
+    Update {
+        id: Uuid,
+        #[serde(flatten)]
+        element: CreateFormElement,
+    },
+    Move {
+        id: Uuid,
+        #[serde(default)]
+        section_id: Option<Uuid>,
+        order_index: i32,
+    },
+    Delete {
+        id: Uuid,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchElementsRequest {
+    pub operations: Vec<ElementOperation>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -70,12 +354,87 @@ pub struct FormResponse {
     pub form_id: Uuid,
     pub respondent_id: Option<Uuid>,
     pub response_data: JsonValue,
+    pub pages_reached: Option<Vec<Uuid>>,
+    pub version_id: Option<Uuid>,
+    pub score: Option<i32>,
+    pub max_score: Option<i32>,
+    pub grading: Option<Json<Grade>>,
+    pub created_at: OffsetDateTime,
+}
+
+/// A file uploaded for a file upload question. It belongs to a response once
+/// the response listing its id is submitted.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UploadedFile {
+    pub id: Uuid,
+    pub form_id: Uuid,
+    pub element_id: Uuid,
+    pub response_id: Option<Uuid>,
+    pub uploaded_by: Option<Uuid>,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
     pub created_at: OffsetDateTime,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateFormResponse {
     pub response_data: JsonValue,
+    // Sections the respondent got to, kept with the response. Which pages'
+    // questions are enforced follows from the answers, not from this.
+    #[serde(default)]
+    pub pages_reached: Option<Vec<Uuid>>,
+}
+
+// What respondents of a version saw. Fields added to FormSection/FormElement
+// later need #[serde(default)] so older snapshots still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormSnapshot {
+    pub title: String,
+    pub description: Option<String>,
+    pub sections: Vec<FormSection>,
+    pub elements: Vec<FormElement>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FormVersion {
+    pub id: Uuid,
+    pub form_id: Uuid,
+    pub version_number: i32,
+    pub snapshot: Json<FormSnapshot>,
+    pub created_by: Option<Uuid>,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FormVersionSummary {
+    pub id: Uuid,
+    pub version_number: i32,
+    pub created_by: Option<Uuid>,
+    pub created_at: OffsetDateTime,
+}
+
+/// A reusable form definition. Built-in templates have no owner.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FormTemplate {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub definition: Json<FormDefinition>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateFormTemplate {
+    pub form_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -91,18 +450,216 @@ pub struct Subscription {
     pub updated_at: OffsetDateTime,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateSubscription {
-    pub plan_type: String,
-    pub paystack_reference: Option<String>,
-}
-
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct FormShare {
     pub id: Uuid,
     pub form_id: Uuid,
-    pub share_type: String,
+    #[sqlx(try_from = "String")]
+    pub share_type: ShareType,
     pub share_token: String,
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    #[serde(skip)]
+    pub password_locked_until: Option<OffsetDateTime>,
     pub expires_at: Option<OffsetDateTime>,
     pub created_at: OffsetDateTime,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareType {
+    // Anyone with the link
+    Link,
+    // Anyone with the link who also knows the password
+    Password,
+    // Only recipients of a personal, single-use invite token
+    Invite,
+}
+
+impl ShareType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareType::Link => "link",
+            ShareType::Password => "password",
+            ShareType::Invite => "invite",
+        }
+    }
+}
+
+impl TryFrom<String> for ShareType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "link" => Ok(ShareType::Link),
+            "password" => Ok(ShareType::Password),
+            "invite" => Ok(ShareType::Invite),
+            _ => Err(format!("unknown share type `{}`", value)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ShareInvite {
+    pub id: Uuid,
+    pub share_id: Uuid,
+    pub email: String,
+    pub token: String,
+    pub response_id: Option<Uuid>,
+    pub responded_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+/// A form's linked Google Sheet.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SheetConnection {
+    pub form_id: Uuid,
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    #[serde(skip)]
+    pub refresh_token: String,
+    pub with_score: bool,
+    #[serde(skip)]
+    pub columns: Json<Vec<Uuid>>,
+    pub headers: Json<Vec<String>>,
+    pub last_synced_at: Option<OffsetDateTime>,
+    pub last_error: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConnectSheet {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    // From the owner's Google authorisation; exchanged for access tokens
+    pub refresh_token: String,
+    // Also send the responses collected so far, not just new ones
+    #[serde(default)]
+    pub backfill: bool,
+}
+
+/// Where a form's events are posted. Payloads are signed with `secret`, which
+/// is only ever shown once, as a `CreatedWebhook`.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub form_id: Uuid,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub events: Vec<String>,
+    pub is_active: bool,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    // Generated when missing; an update without one keeps the old secret
+    #[serde(default)]
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub is_active: Option<bool>,
+}
+
+/// One event sent, or still to be sent, to a webhook, with the outcome of the
+/// latest attempt.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: JsonValue,
+    // "pending", "succeeded" or "failed" once retries run out
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: OffsetDateTime,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub delivered_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+/// How a form's owner is told about new responses.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NotificationSettings {
+    pub form_id: Uuid,
+    // "instant", "hourly", "daily" or "off"
+    pub frequency: String,
+    // When the settings were last changed; never for forms left as they were
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationSettings {
+    pub frequency: String,
+}
+
+/// The email sent to a respondent who gave their address in one of the
+/// form's email questions. `subject` and `body` may use placeholders such as
+/// `{{form.title}}` and `{{answer.<element id>}}`.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConfirmationEmail {
+    pub form_id: Uuid,
+    pub is_enabled: bool,
+    // The form's first email question when not set
+    pub email_element_id: Option<Uuid>,
+    pub subject: String,
+    pub body: String,
+    pub include_answers: bool,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateConfirmationEmail {
+    #[serde(default)]
+    pub is_enabled: Option<bool>,
+    #[serde(default)]
+    pub email_element_id: Option<Uuid>,
+    pub subject: String,
+    pub body: String,
+    #[serde(default)]
+    pub include_answers: Option<bool>,
+}
+
+/// Something that happened to a form's responses, as sent to live feeds.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ResponseEvent {
+    pub id: i64,
+    pub form_id: Uuid,
+    // "response.created" or "response.deleted", or "reset" when a resuming
+    // client has missed events that were pruned
+    pub event: String,
+    pub data: JsonValue,
+    pub created_at: OffsetDateTime,
+}
+
+/// A response saved part way through, for its respondent to finish later.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ResponseDraft {
+    pub id: Uuid,
+    pub form_id: Uuid,
+    pub respondent_id: Option<Uuid>,
+    pub response_data: JsonValue,
+    pub pages_reached: Option<Vec<Uuid>>,
+    pub expires_at: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    // Only filled in when an anonymous draft is first saved; it can't be
+    // shown again
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
+}
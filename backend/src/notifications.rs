@@ -0,0 +1,391 @@
+use std::{fmt::Write, ops::Range, time::Duration};
+
+use sqlx::{PgConnection, PgPool};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    config::MAX_DIGEST_RESPONSES,
+    elements::ElementType,
+    error::AppError,
+    export::{answer_text, DateFormat, ExportLayout, ExportRow, EXPORT_ROWS},
+    mailer,
+    models::{ConfirmationEmail, Form, FormElement, FormResponse},
+};
+
+pub const INSTANT: &str = "instant";
+pub const HOURLY: &str = "hourly";
+pub const DAILY: &str = "daily";
+pub const OFF: &str = "off";
+pub const FREQUENCIES: &[&str] = &[INSTANT, HOURLY, DAILY, OFF];
+
+const DIGEST_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+// Placeholders a confirmation email can use, besides `answer.<element id>`
+const FORM_TITLE: &str = "form.title";
+const RESPONSE_ID: &str = "response.id";
+const SUBMITTED_AT: &str = "response.submitted_at";
+const ANSWER_PREFIX: &str = "answer.";
+
+async fn owner_email(conn: &mut PgConnection, form_id: Uuid, frequency: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT u.email FROM form_notifications n
+         JOIN forms f ON f.id = n.form_id
+         JOIN users u ON u.id = f.user_id
+         WHERE n.form_id = $1 AND n.frequency = $2"
+    )
+    .bind(form_id)
+    .bind(frequency)
+    .fetch_optional(conn)
+    .await
+}
+
+/// Queues an email to the form's owner about a response if they asked to
+/// hear about each one, or marks it for their next digest. Call inside the
+/// transaction storing the response.
+pub async fn response_created(
+    conn: &mut PgConnection,
+    form: &Form,
+    response_id: Uuid,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE form_responses SET digest_pending = TRUE
+         WHERE id = $1 AND EXISTS (
+             SELECT 1 FROM form_notifications WHERE form_id = $2 AND frequency IN ('hourly', 'daily')
+         )"
+    )
+    .bind(response_id)
+    .bind(form.id)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let Some(to) = owner_email(conn, form.id, INSTANT).await.map_err(AppError::DatabaseError)? else {
+        return Ok(());
+    };
+
+    // On the transaction's own connection: it holds the form's row lock, so
+    // waiting on the pool for another could deadlock with other submissions
+    let layout = ExportLayout::load(&mut *conn, form.id, form.is_quiz, None, false).await?;
+    let row = sqlx::query_as::<_, ExportRow>(EXPORT_ROWS)
+        .bind(form.id)
+        .bind(vec![response_id])
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    let subject = format!("New response to \"{}\"", form.title);
+    let mut body = format!("\"{}\" has a new response.\n\n", form.title);
+    write_response(&mut body, &layout, &row);
+
+    mailer::queue(conn, Some(form.id), &to, &subject, &body)
+        .await
+        .map_err(AppError::DatabaseError)
+}
+
+/// Sends hourly and daily digests as they fall due, for as long as the
+/// server runs.
+pub fn spawn_digests(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = send_due_digests(&pool).await {
+                tracing::error!("sending notification digests failed: {}", e);
+            }
+            tokio::time::sleep(DIGEST_POLL_INTERVAL).await;
+        }
+    });
+}
+
+#[derive(sqlx::FromRow)]
+struct DueDigest {
+    form_id: Uuid,
+    frequency: String,
+    digest_sent_at: OffsetDateTime,
+    title: String,
+    is_quiz: bool,
+    email: String,
+}
+
+async fn send_due_digests(pool: &PgPool) -> Result<(), AppError> {
+    loop {
+        let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+        // One form at a time, locked so only one server sends its digest
+        let Some(digest) = sqlx::query_as::<_, DueDigest>(
+            "SELECT n.form_id, n.frequency, n.digest_sent_at, f.title, f.is_quiz, u.email
+             FROM form_notifications n
+             JOIN forms f ON f.id = n.form_id
+             JOIN users u ON u.id = f.user_id
+             WHERE (n.frequency = 'hourly' AND n.digest_sent_at <= NOW() - INTERVAL '1 hour')
+                OR (n.frequency = 'daily' AND n.digest_sent_at <= NOW() - INTERVAL '1 day')
+             ORDER BY n.digest_sent_at
+             LIMIT 1
+             FOR UPDATE OF n SKIP LOCKED"
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?
+        else {
+            return Ok(());
+        };
+
+        queue_digest(&mut tx, &digest).await?;
+        tx.commit().await.map_err(AppError::DatabaseError)?;
+    }
+}
+
+// Covers the responses marked since the last digest, including ones that
+// committed after it ran. Nothing is sent for a quiet period, but it still
+// counts as covered.
+async fn queue_digest(tx: &mut PgConnection, digest: &DueDigest) -> Result<(), AppError> {
+    let (total, ids): (i64, Vec<Uuid>) = sqlx::query_as(
+        "WITH reported AS (
+             UPDATE form_responses SET digest_pending = FALSE
+             WHERE form_id = $1 AND digest_pending
+             RETURNING id, created_at
+         )
+         SELECT COUNT(*), COALESCE((ARRAY_AGG(id ORDER BY created_at, id))[1:$2], '{}')
+         FROM reported"
+    )
+    .bind(digest.form_id)
+    .bind(MAX_DIGEST_RESPONSES as i32)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if total > 0 {
+        let layout = ExportLayout::load(&mut *tx, digest.form_id, digest.is_quiz, None, false).await?;
+        let rows = sqlx::query_as::<_, ExportRow>(EXPORT_ROWS)
+            .bind(digest.form_id)
+            .bind(&ids)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?;
+
+        let digest_name = if digest.frequency == DAILY { "Daily" } else { "Hourly" };
+        let noun = if total == 1 { "response" } else { "responses" };
+        let subject = format!("{} digest: {} new {} to \"{}\"", digest_name, total, noun, digest.title);
+        let mut body = format!(
+            "\"{}\" received {} {} since {}.\n",
+            digest.title,
+            total,
+            noun,
+            DateFormat::Rfc3339.format(digest.digest_sent_at),
+        );
+        for row in &rows {
+            body.push_str("\n----------------------------------------\n\n");
+            write_response(&mut body, &layout, row);
+        }
+        if total > rows.len() as i64 {
+            let _ = write!(body, "\n...and {} more.\n", total - rows.len() as i64);
+        }
+
+        mailer::queue(tx, Some(digest.form_id), &digest.email, &subject, &body)
+            .await
+            .map_err(AppError::DatabaseError)?;
+    }
+
+    sqlx::query("UPDATE form_notifications SET digest_sent_at = NOW() WHERE form_id = $1")
+        .bind(digest.form_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    Ok(())
+}
+
+// Respondent details, then each question's text and answer
+fn write_response(body: &mut String, layout: &ExportLayout, row: &ExportRow) {
+    let _ = writeln!(body, "Submitted: {}", DateFormat::Rfc3339.format(row.created_at));
+    match (&row.respondent_name, &row.respondent_email) {
+        (Some(name), Some(email)) => {
+            let _ = writeln!(body, "Respondent: {} <{}>", name, email);
+        }
+        (None, Some(email)) => {
+            let _ = writeln!(body, "Respondent: {}", email);
+        }
+        _ => body.push_str("Respondent: anonymous\n"),
+    }
+    if let (true, Some(score), Some(max_score)) = (layout.with_score, row.score, row.max_score) {
+        let _ = writeln!(body, "Score: {}/{}", score, max_score);
+    }
+
+    for column in &layout.columns {
+        let answer = row
+            .response_data
+            .get(column.element_id.to_string())
+            .map(answer_text)
+            .filter(|answer| !answer.is_empty())
+            .unwrap_or_else(|| "(no answer)".to_string());
+        let _ = write!(body, "\n{}\n{}\n", column.label, answer);
+    }
+}
+
+/// Queues the form's confirmation email, if it has one turned on, to the
+/// address given in its email question. Call inside the transaction storing
+/// the response; `elements` are the ones it was checked against.
+pub async fn confirm_response(
+    conn: &mut PgConnection,
+    form: &Form,
+    elements: &[FormElement],
+    response: &FormResponse,
+) -> Result<(), AppError> {
+    let Some(confirmation) = sqlx::query_as::<_, ConfirmationEmail>(
+        "SELECT * FROM confirmation_emails WHERE form_id = $1 AND is_enabled"
+    )
+    .bind(form.id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?
+    else {
+        return Ok(());
+    };
+
+    // Nobody to write to if the question was skipped
+    let to = elements
+        .iter()
+        .filter(|element| element.element_type == ElementType::Email)
+        .find(|element| confirmation.email_element_id.is_none_or(|id| id == element.id))
+        .and_then(|element| response.response_data.get(element.id.to_string()))
+        .and_then(|answer| answer.as_str())
+        .map(str::trim)
+        .filter(|address| !address.is_empty());
+    let Some(to) = to else {
+        return Ok(());
+    };
+
+    let value = |name: &str| placeholder_value(name, form, response);
+    let subject = render_template(&confirmation.subject, value).replace(['\r', '\n'], " ");
+    let mut body = render_template(&confirmation.body, value);
+
+    if confirmation.include_answers {
+        body.push_str("\n\n----------------------------------------\nYour answers\n");
+        for element in elements {
+            let answer = response
+                .response_data
+                .get(element.id.to_string())
+                .map(answer_text)
+                .filter(|answer| !answer.is_empty());
+            if let Some(answer) = answer {
+                let _ = write!(body, "\n{}\n{}\n", element.question, answer);
+            }
+        }
+    }
+
+    mailer::queue(conn, Some(form.id), to, &subject, &body)
+        .await
+        .map_err(AppError::DatabaseError)
+}
+
+fn placeholder_value(name: &str, form: &Form, response: &FormResponse) -> Option<String> {
+    match name {
+        FORM_TITLE => Some(form.title.clone()),
+        RESPONSE_ID => Some(response.id.to_string()),
+        SUBMITTED_AT => Some(DateFormat::Rfc3339.format(response.created_at)),
+        _ => {
+            let element_id: Uuid = name.strip_prefix(ANSWER_PREFIX)?.parse().ok()?;
+            Some(
+                response
+                    .response_data
+                    .get(element_id.to_string())
+                    .map(answer_text)
+                    .unwrap_or_default(),
+            )
+        }
+    }
+}
+
+/// The placeholders in `template` that a confirmation email can't fill in:
+/// anything besides `form.title`, `response.id`, `response.submitted_at` and
+/// `answer.<id>` for one of `element_ids`.
+pub fn unknown_placeholders<'a>(template: &'a str, element_ids: &[Uuid]) -> Vec<&'a str> {
+    placeholders(template)
+        .into_iter()
+        .map(|(_, name)| name)
+        .filter(|name| match *name {
+            FORM_TITLE | RESPONSE_ID | SUBMITTED_AT => false,
+            _ => !name
+                .strip_prefix(ANSWER_PREFIX)
+                .and_then(|id| id.parse::<Uuid>().ok())
+                .is_some_and(|id| element_ids.contains(&id)),
+        })
+        .collect()
+}
+
+// Replaces each `{{ name }}` with `value(name)`, leaving the ones it has no
+// value for as they are
+fn render_template(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut copied = 0;
+    for (range, name) in placeholders(template) {
+        rendered.push_str(&template[copied..range.start]);
+        match value(name) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&template[range.clone()]),
+        }
+        copied = range.end;
+    }
+    rendered.push_str(&template[copied..]);
+    rendered
+}
+
+// Where each `{{ name }}` is, and its name without the surrounding spaces
+fn placeholders(template: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = template[from..].find("{{").map(|i| from + i) {
+        let Some(end) = template[start + 2..].find("}}").map(|i| start + 2 + i) else {
+            break;
+        };
+        found.push((start..end + 2, template[start + 2..end].trim()));
+        from = end + 2;
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_found_and_trimmed() {
+        let found = placeholders("Hi {{ answer.x }}, thanks for {{form.title}}!");
+        let names: Vec<_> = found.iter().map(|(_, name)| *name).collect();
+
+        assert_eq!(names, ["answer.x", "form.title"]);
+        assert_eq!(found[0].0, 3..17);
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_ignored() {
+        assert!(placeholders("Hello {{ name").is_empty());
+        assert_eq!(placeholders("{{ a }} then {{ b").len(), 1);
+    }
+
+    #[test]
+    fn render_fills_known_and_keeps_unknown() {
+        let rendered = render_template("{{ form.title }}: {{ mystery }} ({{form.title}})", |name| {
+            (name == FORM_TITLE).then(|| "Survey".to_string())
+        });
+
+        assert_eq!(rendered, "Survey: {{ mystery }} (Survey)");
+    }
+
+    #[test]
+    fn rendered_values_are_not_expanded_again() {
+        let rendered = render_template("{{ form.title }}", |_| Some("{{ form.title }}".to_string()));
+        assert_eq!(rendered, "{{ form.title }}");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_reported() {
+        let known = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        let template = format!(
+            "{{{{ form.title }}}} {{{{ response.id }}}} {{{{ answer.{known} }}}} {{{{ answer.{other} }}}} {{{{ answer.bad }}}} {{{{ user.email }}}}"
+        );
+
+        let unknown = unknown_placeholders(&template, &[known]);
+        assert_eq!(unknown, [format!("answer.{other}").as_str(), "answer.bad", "user.email"]);
+    }
+}
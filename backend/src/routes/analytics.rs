@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+
+use axum::{
+    routing::get,
+    Router,
+    Json,
+    Extension,
+    extract::{Path, Query},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    elements::{ElementOptions, ElementType},
+    versions::{export_columns, ExportColumn},
+};
+
+// How many of the most common answers to list for text questions
+const TOP_TEXT_ANSWERS: i64 = 10;
+// Longest time series returned in one request
+const MAX_BUCKETS: i64 = 1000;
+// How many forms the dashboard lists as most active
+const DASHBOARD_ACTIVE_FORMS: i64 = 5;
+
+// Every non-empty answer to one of the given questions. Binds the form id as
+// $1 and the element ids, as text, as $2.
+const ANSWERS: &str = "
+    WITH answers AS (
+        SELECT kv.key::UUID AS element_id, kv.value
+        FROM form_responses r
+        CROSS JOIN LATERAL jsonb_each(r.response_data) kv
+        WHERE r.form_id = $1
+          AND kv.key = ANY($2)
+          AND kv.value NOT IN ('null'::JSONB, '\"\"'::JSONB, '[]'::JSONB)
+    )";
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/analytics", get(form_analytics))
+        .route("/forms/:id/analytics/timeseries", get(response_timeseries))
+        .route("/dashboard", get(dashboard))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NumericSummary {
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuestionAnalytics {
+    pub element_id: Uuid,
+    pub question: String,
+    pub element_type: ElementType,
+    pub removed: bool,
+    pub answered: i64,
+    // Share of all responses that answered this question
+    pub response_rate: f64,
+    // Choice, rating and scale questions: how often each value was picked,
+    // including options nobody chose
+    pub choices: Option<Vec<ValueCount>>,
+    // Number, rating and scale questions
+    pub numeric: Option<NumericSummary>,
+    // Text questions: the most common answers, compared case-insensitively
+    pub top_answers: Option<Vec<ValueCount>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FormAnalytics {
+    pub total_responses: i64,
+    pub questions: Vec<QuestionAnalytics>,
+}
+
+async fn form_analytics(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<FormAnalytics>, AppError> {
+    // Verify form ownership
+    sqlx::query!(
+        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
+        form_id,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let total_responses = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM form_responses WHERE form_id = $1"
+    )
+    .bind(form_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let columns = export_columns(&mut *pool.acquire().await.map_err(AppError::DatabaseError)?, form_id).await.map_err(AppError::DatabaseError)?;
+    let ids_of = |types: &[ElementType]| -> Vec<String> {
+        columns
+            .iter()
+            .filter(|column| types.contains(&column.element_type))
+            .map(|column| column.element_id.to_string())
+            .collect()
+    };
+
+    let answered: HashMap<Uuid, i64> = sqlx::query_as::<_, (Uuid, i64)>(&format!(
+        "{} SELECT element_id, COUNT(*) FROM answers GROUP BY element_id",
+        ANSWERS
+    ))
+    .bind(form_id)
+    .bind(columns.iter().map(|column| column.element_id.to_string()).collect::<Vec<_>>())
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .into_iter()
+    .collect();
+
+    // Multiple choice answers are arrays; count each selection
+    let mut choices: HashMap<Uuid, Vec<ValueCount>> = HashMap::new();
+    let rows = sqlx::query_as::<_, (Uuid, String, i64)>(&format!(
+        "{} SELECT element_id, choice, COUNT(*)
+           FROM answers
+           CROSS JOIN LATERAL jsonb_array_elements_text(
+               CASE jsonb_typeof(value) WHEN 'array' THEN value ELSE jsonb_build_array(value) END
+           ) choice
+           GROUP BY 1, 2
+           ORDER BY 3 DESC, 2",
+        ANSWERS
+    ))
+    .bind(form_id)
+    .bind(ids_of(&[
+        ElementType::SingleChoice,
+        ElementType::MultipleChoice,
+        ElementType::Dropdown,
+        ElementType::Rating,
+        ElementType::LinearScale,
+    ]))
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+    for (element_id, value, count) in rows {
+        choices.entry(element_id).or_default().push(ValueCount { value, count });
+    }
+
+    let mut numeric: HashMap<Uuid, NumericSummary> = HashMap::new();
+    let rows = sqlx::query_as::<_, (Uuid, f64, f64, f64, f64)>(&format!(
+        "{} SELECT element_id,
+                  AVG(value::FLOAT8),
+                  PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY value::FLOAT8),
+                  MIN(value::FLOAT8),
+                  MAX(value::FLOAT8)
+           FROM answers
+           WHERE jsonb_typeof(value) = 'number'
+           GROUP BY element_id",
+        ANSWERS
+    ))
+    .bind(form_id)
+    .bind(ids_of(&[ElementType::Number, ElementType::Rating, ElementType::LinearScale]))
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+    for (element_id, mean, median, min, max) in rows {
+        numeric.insert(element_id, NumericSummary { mean, median, min, max });
+    }
+
+    let mut top_answers: HashMap<Uuid, Vec<ValueCount>> = HashMap::new();
+    let rows = sqlx::query_as::<_, (Uuid, String, i64)>(&format!(
+        "{} SELECT element_id, answer, count FROM (
+               SELECT element_id, answer, COUNT(*) AS count,
+                      ROW_NUMBER() OVER (PARTITION BY element_id ORDER BY COUNT(*) DESC, answer) AS rank
+               FROM answers
+               CROSS JOIN LATERAL (SELECT LOWER(TRIM(value #>> '{{}}')) AS answer) a
+               WHERE jsonb_typeof(value) = 'string' AND answer <> ''
+               GROUP BY element_id, answer
+           ) ranked
+           WHERE rank <= $3
+           ORDER BY element_id, rank",
+        ANSWERS
+    ))
+    .bind(form_id)
+    .bind(ids_of(&[ElementType::ShortText, ElementType::LongText, ElementType::Email]))
+    .bind(TOP_TEXT_ANSWERS)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+    for (element_id, value, count) in rows {
+        top_answers.entry(element_id).or_default().push(ValueCount { value, count });
+    }
+
+    let questions = columns
+        .into_iter()
+        .map(|column| {
+            let answered = answered.get(&column.element_id).copied().unwrap_or(0);
+            let choices = choice_counts(&column, choices.remove(&column.element_id));
+            let top_answers = match column.element_type {
+                ElementType::ShortText | ElementType::LongText | ElementType::Email => {
+                    Some(top_answers.remove(&column.element_id).unwrap_or_default())
+                }
+                _ => None,
+            };
+
+            QuestionAnalytics {
+                element_id: column.element_id,
+                numeric: numeric.remove(&column.element_id),
+                question: column.label,
+                element_type: column.element_type,
+                removed: column.removed,
+                answered,
+                response_rate: if total_responses > 0 { answered as f64 / total_responses as f64 } else { 0.0 },
+                choices,
+                top_answers,
+            }
+        })
+        .collect();
+
+    Ok(Json(FormAnalytics {
+        total_responses,
+        questions,
+    }))
+}
+
+// Lists every option the question offers in its own order, then any other
+// values respondents gave (such as "other" answers) by popularity
+fn choice_counts(column: &ExportColumn, counted: Option<Vec<ValueCount>>) -> Option<Vec<ValueCount>> {
+    let offered: Vec<String> = match ElementOptions::parse(column.element_type, column.options.as_ref()) {
+        Ok(ElementOptions::Choice(options)) => options.choices,
+        Ok(ElementOptions::Rating(options)) => (1..=options.max).map(|value| value.to_string()).collect(),
+        Ok(ElementOptions::LinearScale(options)) => {
+            (options.min..=options.max).map(|value| value.to_string()).collect()
+        }
+        _ => match column.element_type {
+            ElementType::SingleChoice
+            | ElementType::MultipleChoice
+            | ElementType::Dropdown
+            | ElementType::Rating
+            | ElementType::LinearScale => Vec::new(),
+            _ => return None,
+        },
+    };
+
+    let mut counted = counted.unwrap_or_default();
+    let mut result: Vec<ValueCount> = offered
+        .into_iter()
+        .map(|value| {
+            let count = counted
+                .iter()
+                .position(|other| other.value == value)
+                .map(|position| counted.remove(position).count)
+                .unwrap_or(0);
+            ValueCount { value, count }
+        })
+        .collect();
+    result.extend(counted);
+
+    Some(result)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Hour,
+    Day,
+    Week,
+}
+
+impl Interval {
+    // Also the unit name Postgres expects in date_trunc and intervals
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::Hour => "hour",
+            Interval::Day => "day",
+            Interval::Week => "week",
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            Interval::Hour => Duration::HOUR,
+            Interval::Day => Duration::DAY,
+            Interval::Week => Duration::WEEK,
+        }
+    }
+}
+
+fn default_interval() -> Interval {
+    Interval::Day
+}
+
+// Times are RFC 3339; the range defaults to the 30 days up to now. Buckets
+// start at midnight (or the top of the hour, or Monday) in `tz`.
+#[derive(Debug, Deserialize)]
+pub struct TimeSeriesQuery {
+    #[serde(default = "default_interval")]
+    interval: Interval,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    to: Option<OffsetDateTime>,
+    tz: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TimeBucket {
+    pub start: OffsetDateTime,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeSeries {
+    pub interval: Interval,
+    pub timezone: String,
+    pub from: OffsetDateTime,
+    pub to: OffsetDateTime,
+    pub total: i64,
+    pub buckets: Vec<TimeBucket>,
+}
+
+// Postgres knows the IANA zone names, so let it be the judge
+async fn check_timezone(pool: &PgPool, tz: Option<String>) -> Result<String, AppError> {
+    let tz = tz.unwrap_or_else(|| "UTC".to_string());
+
+    let known = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)"
+    )
+    .bind(&tz)
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if known {
+        Ok(tz)
+    } else {
+        Err(AppError::FieldErrors(vec![FieldError::new("tz", format!("unknown time zone `{}`", tz))]))
+    }
+}
+
+async fn response_timeseries(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Query(query): Query<TimeSeriesQuery>,
+) -> Result<Json<TimeSeries>, AppError> {
+    // Verify form ownership
+    sqlx::query!(
+        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
+        form_id,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let timezone = check_timezone(&pool, query.tz).await?;
+    let to = query.to.unwrap_or_else(OffsetDateTime::now_utc);
+    let from = query.from.unwrap_or(to - Duration::days(30));
+
+    if from >= to {
+        return Err(AppError::FieldErrors(vec![FieldError::new("to", "must be after from")]));
+    }
+    if (to - from).whole_seconds() / query.interval.duration().whole_seconds() >= MAX_BUCKETS {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "interval",
+            format!("the range would need more than {} buckets; use a longer interval", MAX_BUCKETS),
+        )]));
+    }
+
+    // Empty buckets are filled in so charts don't have to. Bucket starts
+    // are found by truncating UTC points in `tz`, not by stepping through
+    // wall clock times, so an hour repeated by a DST change gets two buckets
+    // and a skipped one gets none.
+    let buckets = sqlx::query_as::<_, TimeBucket>(
+        "SELECT bucket AS start, COALESCE(counted.count, 0) AS count
+         FROM (
+             SELECT date_trunc($3, point, $2) AS bucket
+             FROM generate_series($4, $5 - INTERVAL '1 microsecond', make_interval(secs => $6)) point
+             UNION
+             SELECT date_trunc($3, $5 - INTERVAL '1 microsecond', $2)
+         ) buckets
+         LEFT JOIN (
+             SELECT date_trunc($3, created_at, $2) AS bucket, COUNT(*) AS count
+             FROM form_responses
+             WHERE form_id = $1 AND created_at >= $4 AND created_at < $5
+             GROUP BY 1
+         ) counted USING (bucket)
+         ORDER BY bucket"
+    )
+    .bind(form_id)
+    .bind(&timezone)
+    .bind(query.interval.as_str())
+    .bind(from)
+    .bind(to)
+    // Half a bucket; no local hour is shorter than 30 minutes, so no
+    // bucket falls between two points
+    .bind(query.interval.duration().whole_seconds() as f64 / 2.0)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(TimeSeries {
+        interval: query.interval,
+        timezone,
+        from,
+        to,
+        total: buckets.iter().map(|bucket| bucket.count).sum(),
+        buckets,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DashboardQuery {
+    // Decides when "this week" started; defaults to UTC
+    tz: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ActiveForm {
+    pub id: Uuid,
+    pub title: String,
+    pub responses_this_week: i64,
+    pub total_responses: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardStats {
+    pub total_forms: i64,
+    pub total_responses: i64,
+    pub responses_this_week: i64,
+    pub most_active_forms: Vec<ActiveForm>,
+}
+
+async fn dashboard(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Query(query): Query<DashboardQuery>,
+) -> Result<Json<DashboardStats>, AppError> {
+    let timezone = check_timezone(&pool, query.tz).await?;
+
+    let (total_forms, total_responses, responses_this_week) = sqlx::query_as::<_, (i64, i64, i64)>(
+        "SELECT (SELECT COUNT(*) FROM forms WHERE user_id = $1),
+                COUNT(r.id),
+                COUNT(r.id) FILTER (
+                    WHERE r.created_at >= date_trunc('week', NOW() AT TIME ZONE $2) AT TIME ZONE $2
+                )
+         FROM form_responses r
+         JOIN forms f ON f.id = r.form_id
+         WHERE f.user_id = $1"
+    )
+    .bind(auth_user.user_id)
+    .bind(&timezone)
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let most_active_forms = sqlx::query_as::<_, ActiveForm>(
+        "SELECT f.id, f.title,
+                COUNT(r.id) FILTER (
+                    WHERE r.created_at >= date_trunc('week', NOW() AT TIME ZONE $2) AT TIME ZONE $2
+                ) AS responses_this_week,
+                COUNT(r.id) AS total_responses
+         FROM forms f
+         LEFT JOIN form_responses r ON r.form_id = f.id
+         WHERE f.user_id = $1
+         GROUP BY f.id
+         HAVING COUNT(r.id) FILTER (
+             WHERE r.created_at >= date_trunc('week', NOW() AT TIME ZONE $2) AT TIME ZONE $2
+         ) > 0
+         ORDER BY responses_this_week DESC, total_responses DESC, f.title
+         LIMIT $3"
+    )
+    .bind(auth_user.user_id)
+    .bind(&timezone)
+    .bind(DASHBOARD_ACTIVE_FORMS)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(DashboardStats {
+        total_forms,
+        total_responses,
+        responses_this_week,
+        most_active_forms,
+    }))
+}
@@ -3,14 +3,13 @@ use axum::{
     Router,
     Json,
     Extension,
-    extract::Path,
 };
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use validator::Validate;
 
 use crate::{
-    models::{User, CreateUser},
+    models::User,
     error::AppError,
     auth::{hash_password, verify_password, create_token, AuthUser},
     config::Config,
@@ -45,8 +44,8 @@ pub struct AuthResponse {
 }
 
 async fn register(
-    Json(payload): Json<RegisterRequest>,
     Extension(pool): Extension<PgPool>,
+    Json(payload): Json<RegisterRequest>,
 ) -> Result<Json<AuthResponse>, AppError> {
     payload.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
 
@@ -76,8 +75,8 @@ async fn register(
 }
 
 async fn login(
-    Json(payload): Json<LoginRequest>,
     Extension(pool): Extension<PgPool>,
+    Json(payload): Json<LoginRequest>,
 ) -> Result<Json<AuthResponse>, AppError> {
     let user = sqlx::query_as::<_, User>(
         "SELECT * FROM users WHERE email = $1"
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use serde_json::Value as JsonValue;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    models::{Form, CreateForm, CreateFormSection, FormElement, FormSection},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    definitions::{FormDefinition, FormDocument, SCHEMA_VERSION},
+    elements::validate_element,
+    routes::{
+        forms::{check_form_quota, validate_form},
+        sections::validate_section,
+    },
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/import", post(import_form))
+        .route("/forms/:id/definition", get(export_form).put(replace_form))
+}
+
+async fn export_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FormDocument>, AppError> {
+    let mut conn = pool.acquire().await.map_err(AppError::DatabaseError)?;
+
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 AND user_id = $2"
+    )
+    .bind(id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let definition = FormDefinition::load(&mut conn, &form).await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(FormDocument {
+        schema_version: SCHEMA_VERSION,
+        exported_at: Some(OffsetDateTime::now_utc()),
+        form: definition,
+    }))
+}
+
+async fn import_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<JsonValue>,
+) -> Result<Json<Form>, AppError> {
+    let definition = parse_document(payload)?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    check_form_quota(&mut tx, auth_user.user_id).await?;
+    let form = definition
+        .instantiate(&mut tx, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(form))
+}
+
+// Overwrites the live layout and settings. Published versions and the
+// responses recorded against them are left alone.
+async fn replace_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<JsonValue>,
+) -> Result<Json<Form>, AppError> {
+    let definition = parse_document(payload)?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    sqlx::query("SELECT id FROM forms WHERE id = $1 AND user_id = $2 FOR UPDATE")
+        .bind(id)
+        .bind(auth_user.user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let form = definition.replace(&mut tx, id).await.map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(form))
+}
+
+// Parses and validates an uploaded document, reporting every problem with the
+// path of the offending field
+fn parse_document(payload: JsonValue) -> Result<FormDefinition, AppError> {
+    // Check the version on its own first so old or future documents get a
+    // clear message rather than a confusing shape error
+    match payload.get("schema_version").and_then(JsonValue::as_u64) {
+        Some(version) if version == SCHEMA_VERSION as u64 => {}
+        Some(version) => {
+            return Err(AppError::FieldErrors(vec![FieldError::new(
+                "schema_version",
+                format!("version {} is not supported; expected {}", version, SCHEMA_VERSION),
+            )]));
+        }
+        None => {
+            return Err(AppError::FieldErrors(vec![FieldError::new(
+                "schema_version",
+                "must be a positive integer",
+            )]));
+        }
+    }
+
+    let document: FormDocument = serde_path_to_error::deserialize(payload).map_err(|e| {
+        let path = e.path().to_string();
+        AppError::FieldErrors(vec![FieldError::new(path, e.into_inner().to_string())])
+    })?;
+
+    let definition = document.form;
+    validate_definition(&definition)?;
+
+    Ok(definition)
+}
+
+fn validate_definition(definition: &FormDefinition) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+    let settings = &definition.settings;
+
+    // Everything but the title lives under settings in the document
+    let form = validate_form(&CreateForm {
+        title: definition.title.clone(),
+        description: definition.description.clone(),
+        is_public: settings.is_public,
+        allow_anonymous: settings.allow_anonymous,
+        opens_at: settings.opens_at,
+        closes_at: settings.closes_at,
+        closed_message: settings.closed_message.clone(),
+        max_responses: settings.max_responses,
+        max_responses_per_respondent: settings.max_responses_per_respondent,
+        max_responses_per_client: settings.max_responses_per_client,
+        is_quiz: settings.is_quiz,
+        show_score_immediately: settings.show_score_immediately,
+    });
+    collect(&mut errors, |field| if field == "title" { "form." } else { "form.settings." }, form)?;
+
+    let mut ids = HashSet::new();
+    let duplicates = definition
+        .sections
+        .iter()
+        .map(|section| section.id)
+        .chain(definition.elements.iter().map(|element| element.id))
+        .map(|id| !ids.insert(id))
+        .collect::<Vec<_>>();
+    let (section_duplicates, element_duplicates) = duplicates.split_at(definition.sections.len());
+
+    for (index, section) in definition.sections.iter().enumerate() {
+        let prefix = format!("form.sections[{}].", index);
+        if section_duplicates[index] {
+            errors.push(FieldError::new(format!("{}id", prefix), "duplicates another id in the form"));
+        }
+        collect(&mut errors, |_| &prefix, validate_section(&CreateFormSection {
+            title: section.title.clone(),
+            description: section.description.clone(),
+            order_index: section.order_index,
+        }))?;
+    }
+
+    // Element validation works on stored rows, so stand some up from the
+    // definition
+    let now = OffsetDateTime::now_utc();
+    let sections: Vec<FormSection> = definition
+        .sections
+        .iter()
+        .map(|section| FormSection {
+            id: section.id,
+            form_id: Uuid::nil(),
+            title: section.title.clone(),
+            description: section.description.clone(),
+            order_index: section.order_index,
+            created_at: now,
+            updated_at: now,
+        })
+        .collect();
+    let elements: Vec<FormElement> = definition
+        .elements
+        .iter()
+        .map(|definition| {
+            let element = definition.element.clone();
+            FormElement {
+                id: definition.id,
+                form_id: Uuid::nil(),
+                section_id: element.section_id,
+                element_type: element.element_type,
+                question: element.question,
+                required: element.required,
+                options: element.options,
+                order_index: element.order_index,
+                logic: element.logic.map(sqlx::types::Json),
+                legacy_element_type: None,
+                legacy_options: None,
+                correct_answer: element.correct_answer,
+                points: element.points,
+                created_at: now,
+                updated_at: now,
+            }
+        })
+        .collect();
+
+    for (index, element) in definition.elements.iter().enumerate() {
+        let prefix = format!("form.elements[{}].", index);
+        if element_duplicates[index] {
+            errors.push(FieldError::new(format!("{}id", prefix), "duplicates another id in the form"));
+        }
+        let siblings: Vec<FormElement> = elements
+            .iter()
+            .filter(|sibling| sibling.id != element.id)
+            .cloned()
+            .collect();
+        collect(&mut errors, |_| &prefix, validate_element(&element.element, &siblings, &sections))?;
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+// Gathers field errors under the path `prefix` gives for each field; any
+// other error is passed straight up
+fn collect<'a>(
+    errors: &mut Vec<FieldError>,
+    prefix: impl Fn(&str) -> &'a str,
+    result: Result<(), AppError>,
+) -> Result<(), AppError> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(AppError::FieldErrors(found)) => {
+            errors.extend(
+                found
+                    .into_iter()
+                    .map(|error| FieldError::new(format!("{}{}", prefix(&error.field), error.field), error.message)),
+            );
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
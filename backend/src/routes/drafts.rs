@@ -0,0 +1,396 @@
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+    http::HeaderMap,
+};
+use serde_json::Value as JsonValue;
+use sqlx::{PgConnection, PgPool};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    models::{CreateFormResponse, Form, FormResponse, ResponseDraft},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    client::ClientId,
+    config::{Config, MAX_DRAFTS_PER_CLIENT, MAX_DRAFT_BYTES},
+    drafts::{new_resume_token, resume_token_hash},
+    routes::{
+        responses::{check_direct_access, submit_response, Claim},
+        shared::{resolve_share, share_password, ResolvedShare},
+    },
+    sheets::Sheets,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/draft", get(get_draft).put(save_draft).delete(delete_draft))
+        .route("/forms/:id/draft/submit", post(submit_draft))
+        .route(
+            "/shared/:token/draft",
+            get(get_shared_draft).put(save_shared_draft).delete(delete_shared_draft),
+        )
+        .route("/shared/:token/draft/submit", post(submit_shared_draft))
+}
+
+// Whose draft a request is about: the one named by an `X-Resume-Token`
+// header, otherwise the signed-in respondent's
+enum DraftOwner {
+    Token(String),
+    Respondent(Uuid),
+}
+
+fn draft_owner(auth_user: &Option<AuthUser>, headers: &HeaderMap) -> Option<DraftOwner> {
+    let token = headers
+        .get("x-resume-token")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|token| !token.is_empty());
+
+    match (token, auth_user) {
+        (Some(token), _) => Some(DraftOwner::Token(resume_token_hash(token))),
+        (None, Some(auth_user)) => Some(DraftOwner::Respondent(auth_user.user_id)),
+        (None, None) => None,
+    }
+}
+
+fn no_draft() -> AppError {
+    AppError::NotFound("Draft not found".to_string())
+}
+
+async fn find_form(pool: &PgPool, form_id: Uuid) -> Result<Form, AppError> {
+    sqlx::query_as::<_, Form>("SELECT * FROM forms WHERE id = $1")
+        .bind(form_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))
+}
+
+async fn find_draft(pool: &PgPool, form_id: Uuid, owner: &DraftOwner) -> Result<ResponseDraft, AppError> {
+    let (respondent_id, token_hash) = match owner {
+        DraftOwner::Respondent(id) => (Some(*id), None),
+        DraftOwner::Token(hash) => (None, Some(hash.as_str())),
+    };
+
+    sqlx::query_as::<_, ResponseDraft>(
+        "SELECT * FROM response_drafts
+         WHERE form_id = $1 AND (respondent_id = $2 OR resume_token_hash = $3) AND expires_at > NOW()"
+    )
+    .bind(form_id)
+    .bind(respondent_id)
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(no_draft)
+}
+
+// Unlike a submission, a draft may leave out required answers
+fn validate_draft(payload: &CreateFormResponse) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if !payload.response_data.is_object() {
+        errors.push(FieldError::new("response_data", "must be an object of answers"));
+    } else if payload.response_data.to_string().len() > MAX_DRAFT_BYTES {
+        errors.push(FieldError::new(
+            "response_data",
+            format!("must be at most {} KB", MAX_DRAFT_BYTES / 1024),
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+async fn get_draft(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<ResponseDraft>, AppError> {
+    let owner = draft_owner(&auth_user, &headers).ok_or_else(no_draft)?;
+    find_draft(&pool, form_id, &owner).await.map(Json)
+}
+
+// Forms behind a password or invite link only take drafts through the link,
+// like their responses
+async fn save_draft(
+    auth_user: Option<AuthUser>,
+    client: ClientId,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateFormResponse>,
+) -> Result<Json<ResponseDraft>, AppError> {
+    let form = find_form(&pool, form_id).await?;
+    if !form.allow_anonymous && auth_user.is_none() {
+        return Err(AppError::AuthorizationError);
+    }
+    check_direct_access(&pool, &form, auth_user.as_ref().map(|u| u.user_id)).await?;
+
+    store_draft(&pool, &form, &auth_user, &client, &headers, payload).await.map(Json)
+}
+
+async fn delete_draft(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<(), AppError> {
+    discard_draft(&pool, form_id, &auth_user, &headers).await
+}
+
+async fn submit_draft(
+    auth_user: Option<AuthUser>,
+    client: ClientId,
+    Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
+    Path(form_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<FormResponse>, AppError> {
+    let owner = draft_owner(&auth_user, &headers).ok_or_else(no_draft)?;
+    let draft = find_draft(&pool, form_id, &owner).await?;
+
+    let form = find_form(&pool, form_id).await?;
+    let respondent_id = draft.respondent_id.or(auth_user.map(|u| u.user_id));
+    if !form.allow_anonymous && respondent_id.is_none() {
+        return Err(AppError::AuthorizationError);
+    }
+    check_direct_access(&pool, &form, respondent_id).await?;
+
+    finish_draft(&pool, &sheets, &client, &form, respondent_id, draft, None).await.map(Json)
+}
+
+// The same, for a form reached through a share link. The link's password or
+// invite is checked on every call, as for the shared form itself.
+
+async fn get_shared_draft(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ResponseDraft>, AppError> {
+    let ResolvedShare { form, .. } = resolve_share(&pool, &token, share_password(&headers)).await?;
+    let owner = draft_owner(&auth_user, &headers).ok_or_else(no_draft)?;
+    find_draft(&pool, form.id, &owner).await.map(Json)
+}
+
+async fn save_shared_draft(
+    auth_user: Option<AuthUser>,
+    client: ClientId,
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateFormResponse>,
+) -> Result<Json<ResponseDraft>, AppError> {
+    let ResolvedShare { form, .. } = resolve_share(&pool, &token, share_password(&headers)).await?;
+    store_draft(&pool, &form, &auth_user, &client, &headers, payload).await.map(Json)
+}
+
+async fn delete_shared_draft(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<(), AppError> {
+    let ResolvedShare { form, .. } = resolve_share(&pool, &token, share_password(&headers)).await?;
+    discard_draft(&pool, form.id, &auth_user, &headers).await
+}
+
+// An invitee's draft uses up their invite when it's submitted
+async fn submit_shared_draft(
+    auth_user: Option<AuthUser>,
+    client: ClientId,
+    Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<FormResponse>, AppError> {
+    let ResolvedShare { form, invite } = resolve_share(&pool, &token, share_password(&headers)).await?;
+    let owner = draft_owner(&auth_user, &headers).ok_or_else(no_draft)?;
+    let draft = find_draft(&pool, form.id, &owner).await?;
+
+    let respondent_id = draft.respondent_id.or(auth_user.map(|u| u.user_id));
+    finish_draft(&pool, &sheets, &client, &form, respondent_id, draft, invite.map(|invite| invite.id))
+        .await
+        .map(Json)
+}
+
+// Saves over the caller's draft, starting one if they have none. A new
+// anonymous draft comes back with the resume token to send from then on.
+// Each save pushes back when the draft expires.
+async fn store_draft(
+    pool: &PgPool,
+    form: &Form,
+    auth_user: &Option<AuthUser>,
+    client: &ClientId,
+    headers: &HeaderMap,
+    payload: CreateFormResponse,
+) -> Result<ResponseDraft, AppError> {
+    validate_draft(&payload)?;
+    if let Some(reason) = form.closed_reason(OffsetDateTime::now_utc()) {
+        return Err(AppError::FormClosed(reason));
+    }
+
+    let ttl_days = Config::from_env().draft_ttl_days;
+    let response_data: &JsonValue = &payload.response_data;
+
+    let draft = match draft_owner(auth_user, headers) {
+        Some(DraftOwner::Token(hash)) => sqlx::query_as::<_, ResponseDraft>(
+            "UPDATE response_drafts
+             SET response_data = $3, pages_reached = $4, expires_at = NOW() + make_interval(days => $5::int),
+                 updated_at = NOW()
+             WHERE form_id = $1 AND resume_token_hash = $2 AND expires_at > NOW()
+             RETURNING *"
+        )
+        .bind(form.id)
+        .bind(hash)
+        .bind(response_data)
+        .bind(&payload.pages_reached)
+        .bind(ttl_days)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(no_draft)?,
+
+        // An expired draft that hasn't been cleaned up yet starts over
+        Some(DraftOwner::Respondent(respondent_id)) => sqlx::query_as::<_, ResponseDraft>(
+            "INSERT INTO response_drafts (form_id, respondent_id, response_data, pages_reached, expires_at)
+             VALUES ($1, $2, $3, $4, NOW() + make_interval(days => $5::int))
+             ON CONFLICT (form_id, respondent_id) WHERE respondent_id IS NOT NULL DO UPDATE
+             SET response_data = $3, pages_reached = $4, expires_at = NOW() + make_interval(days => $5::int),
+                 created_at = CASE WHEN response_drafts.expires_at > NOW()
+                                   THEN response_drafts.created_at ELSE NOW() END,
+                 updated_at = NOW()
+             RETURNING *"
+        )
+        .bind(form.id)
+        .bind(respondent_id)
+        .bind(response_data)
+        .bind(&payload.pages_reached)
+        .bind(ttl_days)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::DatabaseError)?,
+
+        None => {
+            let client_key = client.key_for(form.id);
+            let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+            if let Some(client_key) = &client_key {
+                check_client_drafts(&mut tx, form, client_key).await?;
+            }
+
+            let token = new_resume_token();
+            let mut draft = sqlx::query_as::<_, ResponseDraft>(
+                "INSERT INTO response_drafts (form_id, resume_token_hash, response_data, pages_reached, expires_at, client_key)
+                 VALUES ($1, $2, $3, $4, NOW() + make_interval(days => $5::int), $6)
+                 RETURNING *"
+            )
+            .bind(form.id)
+            .bind(resume_token_hash(&token))
+            .bind(response_data)
+            .bind(&payload.pages_reached)
+            .bind(ttl_days)
+            .bind(&client_key)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?;
+            tx.commit().await.map_err(AppError::DatabaseError)?;
+            draft.resume_token = Some(token);
+            draft
+        }
+    };
+
+    Ok(draft)
+}
+
+// A device that can't submit any more responses, or already has drafts on
+// the go, can't start another anonymous draft. The form row is locked, as for
+// a submission, so concurrent saves can't both slip under the limit.
+async fn check_client_drafts(conn: &mut PgConnection, form: &Form, client_key: &str) -> Result<(), AppError> {
+    sqlx::query("SELECT id FROM forms WHERE id = $1 FOR UPDATE")
+        .bind(form.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    if let Some(max) = form.max_responses_per_client {
+        let responses = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM form_responses WHERE form_id = $1 AND client_key = $2"
+        )
+        .bind(form.id)
+        .bind(client_key)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if responses >= max as i64 {
+            return Err(AppError::ResponseLimitReached(
+                "This device has already submitted the maximum number of responses to this form".to_string(),
+            ));
+        }
+    }
+
+    let drafts = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM response_drafts WHERE form_id = $1 AND client_key = $2 AND expires_at > NOW()"
+    )
+    .bind(form.id)
+    .bind(client_key)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if drafts >= MAX_DRAFTS_PER_CLIENT {
+        return Err(AppError::ResponseLimitReached(
+            "This device already has the maximum number of drafts for this form".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn discard_draft(
+    pool: &PgPool,
+    form_id: Uuid,
+    auth_user: &Option<AuthUser>,
+    headers: &HeaderMap,
+) -> Result<(), AppError> {
+    let owner = draft_owner(auth_user, headers).ok_or_else(no_draft)?;
+    let draft = find_draft(pool, form_id, &owner).await?;
+
+    sqlx::query("DELETE FROM response_drafts WHERE id = $1")
+        .bind(draft.id)
+        .execute(pool)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    Ok(())
+}
+
+// Submits the draft as it was last saved, with all the checks of a normal
+// submission. The draft is deleted along with storing the response.
+async fn finish_draft(
+    pool: &PgPool,
+    sheets: &Sheets,
+    client: &ClientId,
+    form: &Form,
+    respondent_id: Option<Uuid>,
+    draft: ResponseDraft,
+    invite_id: Option<Uuid>,
+) -> Result<FormResponse, AppError> {
+    let mut claims = vec![Claim::Draft(draft.id)];
+    claims.extend(invite_id.map(Claim::Invite));
+
+    let payload = CreateFormResponse {
+        response_data: draft.response_data,
+        pages_reached: draft.pages_reached,
+    };
+    submit_response(pool, sheets, form, respondent_id, client, claims, payload).await
+}
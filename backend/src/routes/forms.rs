@@ -1,20 +1,33 @@
+use std::collections::{HashMap, HashSet};
+
 use axum::{
     routing::{get, post, put, delete},
     Router,
     Json,
     Extension,
-    extract::{Path, Query},
+    extract::Path,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{types::Json as SqlJson, PgConnection, PgPool};
 use uuid::Uuid;
-use validator::Validate;
 
 use crate::{
-    models::{Form, CreateForm, FormElement, CreateFormElement, FormShare},
-    error::AppError,
-    auth::AuthUser,
-    config::{Config, FREE_PLAN_MAX_FORMS},
+    models::{
+        Form, CreateForm, FormStatus, FormElement, CreateFormElement, BatchElementsRequest, ElementOperation, FormShare,
+        ShareInvite, ShareType,
+    },
+    error::{AppError, FieldError},
+    auth::{hash_password, AuthUser},
+    config::{FREE_PLAN_MAX_FORMS, MAX_BATCH_OPERATIONS, MIN_SHARE_PASSWORD_LEN},
+    db::{fetch_elements, fetch_sections, FORM_WITH_REMAINING},
+    definitions::FormDefinition,
+    elements::validate_element,
+    logic::{element_position, validate_layout, validate_logic},
+    grading::hide_answer_keys,
+    storage::Storage,
+    uploads::delete_blobs,
+    versions::{create_version, published_layout},
+    webhooks,
 };
 
 pub fn router() -> Router {
@@ -24,56 +37,116 @@ pub fn router() -> Router {
         .route("/forms/:id", get(get_form))
         .route("/forms/:id", put(update_form))
         .route("/forms/:id", delete(delete_form))
+        .route("/forms/:id/publish", post(publish_form))
+        .route("/forms/:id/close", post(close_form))
+        .route("/forms/:id/archive", post(archive_form))
+        .route("/forms/:id/restore", post(restore_form))
+        .route("/forms/:id/duplicate", post(duplicate_form))
         .route("/forms/:id/elements", post(create_element))
         .route("/forms/:id/elements", get(list_elements))
+        .route("/forms/:id/elements/batch", post(batch_elements))
         .route("/forms/:id/elements/:element_id", put(update_element))
         .route("/forms/:id/elements/:element_id", delete(delete_element))
         .route("/forms/:id/share", post(create_share))
         .route("/forms/:id/share", get(get_share))
+        .route("/forms/:id/share/:share_id/invites", post(create_invites))
+        .route("/forms/:id/share/:share_id/invites", get(list_invites))
 }
 
-async fn create_form(
-    auth_user: AuthUser,
-    Extension(pool): Extension<PgPool>,
-    Json(payload): Json<CreateForm>,
-) -> Result<Json<Form>, AppError> {
-    // Check form limit for free plan
-    let form_count = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM forms WHERE user_id = $1"
+pub fn validate_form(payload: &CreateForm) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if payload.title.trim().is_empty() {
+        errors.push(FieldError::new("title", "must not be empty"));
+    }
+    if let (Some(opens_at), Some(closes_at)) = (payload.opens_at, payload.closes_at) {
+        if opens_at >= closes_at {
+            errors.push(FieldError::new("closes_at", "must be after opens_at"));
+        }
+    }
+    for (field, limit) in [
+        ("max_responses", payload.max_responses),
+        ("max_responses_per_respondent", payload.max_responses_per_respondent),
+        ("max_responses_per_client", payload.max_responses_per_client),
+    ] {
+        if limit.map(|limit| limit < 1).unwrap_or(false) {
+            errors.push(FieldError::new(field, "must be at least 1"));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+/// Fails if the user's plan doesn't allow another form. Every way of creating
+/// a form goes through this, in the transaction that inserts it; the user row
+/// stays locked until then so concurrent requests can't both squeeze in.
+pub async fn check_form_quota(conn: &mut PgConnection, user_id: Uuid) -> Result<(), AppError> {
+    let plan = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT subscription_plan FROM users WHERE id = $1 FOR UPDATE"
     )
-    .bind(auth_user.user_id)
-    .fetch_one(&pool)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
     .await
     .map_err(AppError::DatabaseError)?;
 
-    let user = sqlx::query!(
-        "SELECT subscription_plan FROM users WHERE id = $1",
-        auth_user.user_id
+    // Check form limit for free plan
+    let form_count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM forms WHERE user_id = $1"
     )
-    .fetch_one(&pool)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
     .await
     .map_err(AppError::DatabaseError)?;
 
-    if user.subscription_plan == "free" && form_count >= FREE_PLAN_MAX_FORMS {
+    if plan.as_deref() == Some("free") && form_count >= FREE_PLAN_MAX_FORMS {
         return Err(AppError::ValidationError(
             "Free plan users can only create up to 3 forms".to_string()
         ));
     }
 
+    Ok(())
+}
+
+async fn create_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<CreateForm>,
+) -> Result<Json<Form>, AppError> {
+    validate_form(&payload)?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    check_form_quota(&mut tx, auth_user.user_id).await?;
+
     let form = sqlx::query_as::<_, Form>(
-        "INSERT INTO forms (user_id, title, description, is_public, allow_anonymous)
-         VALUES ($1, $2, $3, $4, $5)
-         RETURNING *"
+        "INSERT INTO forms
+         (user_id, title, description, is_public, allow_anonymous, opens_at, closes_at, closed_message,
+          max_responses, max_responses_per_respondent, max_responses_per_client, is_quiz, show_score_immediately)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+         RETURNING *, max_responses::BIGINT AS remaining_responses"
     )
     .bind(auth_user.user_id)
     .bind(&payload.title)
     .bind(&payload.description)
     .bind(payload.is_public)
     .bind(payload.allow_anonymous)
-    .fetch_one(&pool)
+    .bind(payload.opens_at)
+    .bind(payload.closes_at)
+    .bind(&payload.closed_message)
+    .bind(payload.max_responses)
+    .bind(payload.max_responses_per_respondent)
+    .bind(payload.max_responses_per_client)
+    .bind(payload.is_quiz)
+    .bind(payload.show_score_immediately)
+    .fetch_one(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?;
 
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
     Ok(Json(form))
 }
 
@@ -82,7 +155,7 @@ async fn list_forms(
     Extension(pool): Extension<PgPool>,
 ) -> Result<Json<Vec<Form>>, AppError> {
     let forms = sqlx::query_as::<_, Form>(
-        "SELECT * FROM forms WHERE user_id = $1 ORDER BY created_at DESC"
+        &format!("SELECT {} FROM forms WHERE user_id = $1 ORDER BY created_at DESC", FORM_WITH_REMAINING)
     )
     .bind(auth_user.user_id)
     .fetch_all(&pool)
@@ -98,7 +171,7 @@ async fn get_form(
     Path(id): Path<Uuid>,
 ) -> Result<Json<Form>, AppError> {
     let form = sqlx::query_as::<_, Form>(
-        "SELECT * FROM forms WHERE id = $1 AND (user_id = $2 OR is_public = true)"
+        &format!("SELECT {} FROM forms WHERE id = $1 AND (user_id = $2 OR is_public = true)", FORM_WITH_REMAINING)
     )
     .bind(id)
     .bind(auth_user.user_id)
@@ -116,16 +189,29 @@ async fn update_form(
     Path(id): Path<Uuid>,
     Json(payload): Json<CreateForm>,
 ) -> Result<Json<Form>, AppError> {
-    let form = sqlx::query_as::<_, Form>(
+    validate_form(&payload)?;
+
+    let form = sqlx::query_as::<_, Form>(&format!(
         "UPDATE forms 
-         SET title = $1, description = $2, is_public = $3, allow_anonymous = $4, updated_at = NOW()
-         WHERE id = $5 AND user_id = $6
-         RETURNING *"
-    )
+         SET title = $1, description = $2, is_public = $3, allow_anonymous = $4,
+             opens_at = $5, closes_at = $6, closed_message = $7, max_responses = $8,
+             max_responses_per_respondent = $9, max_responses_per_client = $10, is_quiz = $11,
+             show_score_immediately = $12, updated_at = NOW()
+         WHERE id = $13 AND user_id = $14
+         RETURNING {}", FORM_WITH_REMAINING
+    ))
     .bind(&payload.title)
     .bind(&payload.description)
     .bind(payload.is_public)
     .bind(payload.allow_anonymous)
+    .bind(payload.opens_at)
+    .bind(payload.closes_at)
+    .bind(&payload.closed_message)
+    .bind(payload.max_responses)
+    .bind(payload.max_responses_per_respondent)
+    .bind(payload.max_responses_per_client)
+    .bind(payload.is_quiz)
+    .bind(payload.show_score_immediately)
     .bind(id)
     .bind(auth_user.user_id)
     .fetch_optional(&pool)
@@ -139,14 +225,26 @@ async fn update_form(
 async fn delete_form(
     auth_user: AuthUser,
     Extension(pool): Extension<PgPool>,
+    Extension(storage): Extension<Storage>,
     Path(id): Path<Uuid>,
 ) -> Result<(), AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    // Upload rows go with the form; their files are removed after commit
+    let storage_keys: Vec<String> = sqlx::query_scalar(
+        "SELECT storage_key FROM file_uploads WHERE form_id = $1"
+    )
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
     let result = sqlx::query!(
         "DELETE FROM forms WHERE id = $1 AND user_id = $2",
         id,
         auth_user.user_id
     )
-    .execute(&pool)
+    .execute(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?;
 
@@ -154,41 +252,182 @@ async fn delete_form(
         return Err(AppError::NotFound("Form not found".to_string()));
     }
 
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    delete_blobs(&storage, &storage_keys).await;
+
     Ok(())
 }
 
-async fn create_element(
+async fn transition_form(
+    pool: &PgPool,
+    user_id: Uuid,
+    form_id: Uuid,
+    next: FormStatus,
+) -> Result<Form, AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 AND user_id = $2 FOR UPDATE"
+    )
+    .bind(form_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    if !form.status.can_transition_to(next) {
+        return Err(AppError::ValidationError(format!(
+            "A {} form cannot be moved to {}",
+            form.status.as_str(),
+            next.as_str()
+        )));
+    }
+
+    // Publishing a draft snapshots what respondents will be answering
+    if form.status == FormStatus::Draft && next == FormStatus::Published {
+        create_version(&mut tx, form_id, user_id)
+            .await
+            .map_err(AppError::DatabaseError)?;
+    }
+
+    let was_published = form.status == FormStatus::Published;
+
+    // A form published again can announce its next closing
+    let form = sqlx::query_as::<_, Form>(&format!(
+        "UPDATE forms
+         SET status = $1, updated_at = NOW(),
+             close_announced_at = CASE WHEN $1 = 'published' THEN NULL ELSE close_announced_at END
+         WHERE id = $2
+         RETURNING {}", FORM_WITH_REMAINING
+    ))
+    .bind(next.as_str())
+    .bind(form_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if was_published && matches!(next, FormStatus::Closed | FormStatus::Archived) {
+        webhooks::form_closed(&mut tx, &form)
+            .await
+            .map_err(AppError::DatabaseError)?;
+    }
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(form)
+}
+
+async fn publish_form(
     auth_user: AuthUser,
     Extension(pool): Extension<PgPool>,
-    Path(form_id): Path<Uuid>,
-    Json(payload): Json<CreateFormElement>,
-) -> Result<Json<FormElement>, AppError> {
-    // Verify form ownership
-    let form = sqlx::query!(
-        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
-        form_id,
-        auth_user.user_id
+    Path(id): Path<Uuid>,
+) -> Result<Json<Form>, AppError> {
+    let form = transition_form(&pool, auth_user.user_id, id, FormStatus::Published).await?;
+
+    Ok(Json(form))
+}
+
+async fn close_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Form>, AppError> {
+    let form = transition_form(&pool, auth_user.user_id, id, FormStatus::Closed).await?;
+
+    Ok(Json(form))
+}
+
+async fn archive_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Form>, AppError> {
+    let form = transition_form(&pool, auth_user.user_id, id, FormStatus::Archived).await?;
+
+    Ok(Json(form))
+}
+
+// Brings an archived form back as a draft
+async fn restore_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Form>, AppError> {
+    let form = transition_form(&pool, auth_user.user_id, id, FormStatus::Draft).await?;
+
+    Ok(Json(form))
+}
+
+async fn duplicate_form(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Form>, AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    check_form_quota(&mut tx, auth_user.user_id).await?;
+
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 AND user_id = $2"
     )
-    .fetch_optional(&pool)
+    .bind(id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?
     .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
 
+    let mut definition = FormDefinition::load(&mut tx, &form).await.map_err(AppError::DatabaseError)?;
+    definition.title = format!("{} (copy)", definition.title);
+
+    let copy = definition
+        .instantiate(&mut tx, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(copy))
+}
+
+async fn create_element(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<CreateFormElement>,
+) -> Result<Json<FormElement>, AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    let siblings = fetch_elements(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+    let sections = fetch_sections(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+
+    validate_element(&payload, &siblings, &sections)?;
+
     let element = sqlx::query_as::<_, FormElement>(
-        "INSERT INTO form_elements (form_id, element_type, question, required, options, order_index)
-         VALUES ($1, $2, $3, $4, $5, $6)
+        "INSERT INTO form_elements
+         (form_id, section_id, element_type, question, required, options, order_index, logic, correct_answer, points)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
          RETURNING *"
     )
     .bind(form_id)
-    .bind(&payload.element_type)
+    .bind(payload.section_id)
+    .bind(payload.element_type.as_str())
     .bind(&payload.question)
     .bind(payload.required)
     .bind(&payload.options)
     .bind(payload.order_index)
-    .fetch_one(&pool)
+    .bind(payload.logic.as_ref().map(SqlJson))
+    .bind(&payload.correct_answer)
+    .bind(payload.points)
+    .fetch_one(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?;
 
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
     Ok(Json(element))
 }
 
@@ -199,7 +438,7 @@ async fn list_elements(
 ) -> Result<Json<Vec<FormElement>>, AppError> {
     // Verify form access
     let form = sqlx::query!(
-        "SELECT id FROM forms WHERE id = $1 AND (user_id = $2 OR is_public = true)",
+        "SELECT user_id FROM forms WHERE id = $1 AND (user_id = $2 OR is_public = true)",
         form_id,
         auth_user.user_id
     )
@@ -208,13 +447,14 @@ async fn list_elements(
     .map_err(AppError::DatabaseError)?
     .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
 
-    let elements = sqlx::query_as::<_, FormElement>(
-        "SELECT * FROM form_elements WHERE form_id = $1 ORDER BY order_index"
-    )
-    .bind(form_id)
-    .fetch_all(&pool)
-    .await
-    .map_err(AppError::DatabaseError)?;
+    // Owners edit the live elements; everyone else sees what is published
+    let elements = if form.user_id == auth_user.user_id {
+        fetch_elements(&pool, form_id).await.map_err(AppError::DatabaseError)?
+    } else {
+        let mut elements = published_layout(&pool, form_id).await.map_err(AppError::DatabaseError)?.elements;
+        hide_answer_keys(&mut elements);
+        elements
+    };
 
     Ok(Json(elements))
 }
@@ -225,26 +465,45 @@ async fn update_element(
     Path((form_id, element_id)): Path<(Uuid, Uuid)>,
     Json(payload): Json<CreateFormElement>,
 ) -> Result<Json<FormElement>, AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    let mut siblings = fetch_elements(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+    siblings.retain(|element| element.id != element_id);
+    let sections = fetch_sections(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+
+    validate_element(&payload, &siblings, &sections)?;
+
     let element = sqlx::query_as::<_, FormElement>(
         "UPDATE form_elements 
-         SET element_type = $1, question = $2, required = $3, options = $4, order_index = $5, updated_at = NOW()
-         WHERE id = $6 AND form_id = $7 
-         AND EXISTS (SELECT 1 FROM forms WHERE id = $7 AND user_id = $8)
+         SET element_type = $1, question = $2, required = $3, options = $4, order_index = $5,
+             logic = $6, section_id = $7, correct_answer = $8, points = $9,
+             legacy_element_type = NULL, legacy_options = NULL, updated_at = NOW()
+         WHERE id = $10 AND form_id = $11 
+         AND EXISTS (SELECT 1 FROM forms WHERE id = $11 AND user_id = $12)
          RETURNING *"
     )
-    .bind(&payload.element_type)
+    .bind(payload.element_type.as_str())
     .bind(&payload.question)
     .bind(payload.required)
     .bind(&payload.options)
     .bind(payload.order_index)
+    .bind(payload.logic.as_ref().map(SqlJson))
+    .bind(payload.section_id)
+    .bind(&payload.correct_answer)
+    .bind(payload.points)
     .bind(element_id)
     .bind(form_id)
     .bind(auth_user.user_id)
-    .fetch_optional(&pool)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?
     .ok_or_else(|| AppError::NotFound("Element not found".to_string()))?;
 
+    // Other elements' logic may point at this one's old place
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
     Ok(Json(element))
 }
 
@@ -253,15 +512,15 @@ async fn delete_element(
     Extension(pool): Extension<PgPool>,
     Path((form_id, element_id)): Path<(Uuid, Uuid)>,
 ) -> Result<(), AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
     let result = sqlx::query!(
-        "DELETE FROM form_elements 
-         WHERE id = $1 AND form_id = $2
-         AND EXISTS (SELECT 1 FROM forms WHERE id = $2 AND user_id = $3)",
+        "DELETE FROM form_elements WHERE id = $1 AND form_id = $2",
         element_id,
-        form_id,
-        auth_user.user_id
+        form_id
     )
-    .execute(&pool)
+    .execute(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?;
 
@@ -269,13 +528,305 @@ async fn delete_element(
         return Err(AppError::NotFound("Element not found".to_string()));
     }
 
+    // Refused while other elements' logic still refers to it
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
     Ok(())
 }
 
+/// Locks the form against concurrent layout edits, checking it's the user's.
+pub async fn lock_owned_form(conn: &mut PgConnection, form_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    sqlx::query("SELECT id FROM forms WHERE id = $1 AND user_id = $2 FOR UPDATE")
+        .bind(form_id)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    Ok(())
+}
+
+/// Rejects a layout edit that leaves any element's logic referring to a
+/// question that's gone or no longer in the right place. Call before
+/// committing the edit.
+pub async fn check_layout(conn: &mut PgConnection, form_id: Uuid) -> Result<(), AppError> {
+    let sections = fetch_sections(&mut *conn, form_id).await.map_err(AppError::DatabaseError)?;
+    let elements = fetch_elements(&mut *conn, form_id).await.map_err(AppError::DatabaseError)?;
+
+    let errors = validate_layout(&elements, &sections);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+// Applies a list of element edits in one transaction, then renumbers every
+// section's elements from zero. Nothing is written unless the whole batch is
+// valid.
+async fn batch_elements(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<BatchElementsRequest>,
+) -> Result<Json<Vec<FormElement>>, AppError> {
+    if payload.operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "operations",
+            format!("must contain at most {} operations", MAX_BATCH_OPERATIONS),
+        )]));
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    // Serialises concurrent batches against the same form
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    let sections = fetch_sections(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+    let mut elements = fetch_elements(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+    let original: HashMap<Uuid, (Option<Uuid>, i32)> = elements
+        .iter()
+        .map(|element| (element.id, (element.section_id, element.order_index)))
+        .collect();
+
+    let mut errors = Vec::new();
+    let mut deleted = Vec::new();
+    // The last operation that created, updated or moved each element
+    let mut touched: HashMap<Uuid, usize> = HashMap::new();
+    let mut edited = HashSet::new();
+    let now = time::OffsetDateTime::now_utc();
+
+    for (index, operation) in payload.operations.into_iter().enumerate() {
+        let not_found = || FieldError::new(format!("operations[{}].id", index), "element is not part of this form");
+
+        // Positions are renumbered before validation, so check them here
+        let order_index = match &operation {
+            ElementOperation::Create { element } | ElementOperation::Update { element, .. } => element.order_index,
+            ElementOperation::Move { order_index, .. } => *order_index,
+            ElementOperation::Delete { .. } => 0,
+        };
+        if order_index < 0 {
+            errors.push(FieldError::new(format!("operations[{}].order_index", index), "must not be negative"));
+        }
+
+        match operation {
+            ElementOperation::Create { element } => {
+                let created = FormElement {
+                    id: Uuid::new_v4(),
+                    form_id,
+                    section_id: element.section_id,
+                    element_type: element.element_type,
+                    question: element.question,
+                    required: element.required,
+                    options: element.options,
+                    order_index,
+                    logic: element.logic.map(SqlJson),
+                    legacy_element_type: None,
+                    legacy_options: None,
+                    correct_answer: element.correct_answer,
+                    points: element.points,
+                    created_at: now,
+                    updated_at: now,
+                };
+                touched.insert(created.id, index);
+                edited.insert(created.id);
+                place_element(&mut elements, created, order_index);
+            }
+            ElementOperation::Update { id, element } => match take_element(&mut elements, id) {
+                Some(mut existing) => {
+                    existing.section_id = element.section_id;
+                    existing.element_type = element.element_type;
+                    existing.question = element.question;
+                    existing.required = element.required;
+                    existing.options = element.options;
+                    existing.logic = element.logic.map(SqlJson);
+                    existing.legacy_element_type = None;
+                    existing.legacy_options = None;
+                    existing.correct_answer = element.correct_answer;
+                    existing.points = element.points;
+                    touched.insert(id, index);
+                    edited.insert(id);
+                    place_element(&mut elements, existing, order_index);
+                }
+                None => errors.push(not_found()),
+            },
+            ElementOperation::Move { id, section_id, .. } => match take_element(&mut elements, id) {
+                Some(mut existing) => {
+                    if section_id.map(|id| !sections.iter().any(|section| section.id == id)).unwrap_or(false) {
+                        errors.push(FieldError::new(
+                            format!("operations[{}].section_id", index),
+                            "section is not part of this form",
+                        ));
+                    }
+                    existing.section_id = section_id;
+                    touched.insert(id, index);
+                    place_element(&mut elements, existing, order_index);
+                }
+                None => errors.push(not_found()),
+            },
+            ElementOperation::Delete { id } => match take_element(&mut elements, id) {
+                Some(_) => {
+                    touched.remove(&id);
+                    edited.remove(&id);
+                    deleted.push(id);
+                }
+                None => errors.push(not_found()),
+            },
+        }
+    }
+
+    // Close up gaps and duplicates left by earlier edits as well as this batch
+    let mut next_index: HashMap<Option<Uuid>, i32> = HashMap::new();
+    for element in &mut elements {
+        let position = next_index.entry(element.section_id).or_insert(0);
+        element.order_index = *position;
+        *position += 1;
+    }
+
+    // Validate against the final layout so logic is checked against where
+    // elements end up, and against anything the batch deleted
+    for element in &elements {
+        let prefix = match touched.get(&element.id) {
+            Some(index) => format!("operations[{}].", index),
+            None => format!("elements[{}].", element.id),
+        };
+        let siblings: Vec<FormElement> = elements
+            .iter()
+            .filter(|sibling| sibling.id != element.id)
+            .cloned()
+            .collect();
+
+        let found = if edited.contains(&element.id) {
+            match validate_element(&CreateFormElement::from(element.clone()), &siblings, &sections) {
+                Err(AppError::FieldErrors(found)) => found,
+                _ => Vec::new(),
+            }
+        } else if let Some(logic) = &element.logic {
+            let position = element_position(element.section_id, element.order_index, &sections);
+            validate_logic(logic, position, &siblings, &sections)
+        } else {
+            Vec::new()
+        };
+
+        errors.extend(
+            found
+                .into_iter()
+                .map(|error| FieldError::new(format!("{}{}", prefix, error.field), error.message)),
+        );
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::FieldErrors(errors));
+    }
+
+    sqlx::query("DELETE FROM form_elements WHERE form_id = $1 AND id = ANY($2)")
+        .bind(form_id)
+        .bind(&deleted)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    for element in &elements {
+        match original.get(&element.id) {
+            None => {
+                sqlx::query(
+                    "INSERT INTO form_elements
+                     (id, form_id, section_id, element_type, question, required, options, order_index, logic,
+                      correct_answer, points)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+                )
+                .bind(element.id)
+                .bind(form_id)
+                .bind(element.section_id)
+                .bind(element.element_type.as_str())
+                .bind(&element.question)
+                .bind(element.required)
+                .bind(&element.options)
+                .bind(element.order_index)
+                .bind(&element.logic)
+                .bind(&element.correct_answer)
+                .bind(element.points)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::DatabaseError)?;
+            }
+            Some(_) if edited.contains(&element.id) => {
+                sqlx::query(
+                    "UPDATE form_elements
+                     SET element_type = $1, question = $2, required = $3, options = $4, order_index = $5,
+                         logic = $6, section_id = $7, correct_answer = $8, points = $9,
+                         legacy_element_type = NULL, legacy_options = NULL, updated_at = NOW()
+                     WHERE id = $10"
+                )
+                .bind(element.element_type.as_str())
+                .bind(&element.question)
+                .bind(element.required)
+                .bind(&element.options)
+                .bind(element.order_index)
+                .bind(&element.logic)
+                .bind(element.section_id)
+                .bind(&element.correct_answer)
+                .bind(element.points)
+                .bind(element.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::DatabaseError)?;
+            }
+            Some(&(section_id, order_index)) if (section_id, order_index) != (element.section_id, element.order_index) => {
+                sqlx::query(
+                    "UPDATE form_elements SET section_id = $1, order_index = $2, updated_at = NOW() WHERE id = $3"
+                )
+                .bind(element.section_id)
+                .bind(element.order_index)
+                .bind(element.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::DatabaseError)?;
+            }
+            Some(_) => {}
+        }
+    }
+
+    let elements = fetch_elements(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(elements))
+}
+
+fn take_element(elements: &mut Vec<FormElement>, id: Uuid) -> Option<FormElement> {
+    let position = elements.iter().position(|element| element.id == id)?;
+    Some(elements.remove(position))
+}
+
+// Inserts `element` so it is the `order_index`th element of its section,
+// or last if the section is shorter than that
+fn place_element(elements: &mut Vec<FormElement>, element: FormElement, order_index: i32) {
+    let position = elements
+        .iter()
+        .enumerate()
+        .filter(|(_, other)| other.section_id == element.section_id)
+        .map(|(position, _)| position)
+        .nth(order_index.max(0) as usize)
+        .or_else(|| {
+            elements
+                .iter()
+                .rposition(|other| other.section_id == element.section_id)
+                .map(|last| last + 1)
+        })
+        .unwrap_or(elements.len());
+
+    elements.insert(position, element);
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShareRequest {
-    pub share_type: String,
+    pub share_type: ShareType,
     pub expires_at: Option<time::OffsetDateTime>,
+    // Required for password shares, ignored otherwise
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
 async fn create_share(
@@ -285,7 +836,7 @@ async fn create_share(
     Json(payload): Json<ShareRequest>,
 ) -> Result<Json<FormShare>, AppError> {
     // Verify form ownership
-    let form = sqlx::query!(
+    sqlx::query!(
         "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
         form_id,
         auth_user.user_id
@@ -295,17 +846,31 @@ async fn create_share(
     .map_err(AppError::DatabaseError)?
     .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
 
+    let password_hash = match (payload.share_type, payload.password.as_deref()) {
+        (ShareType::Password, Some(password)) if password.chars().count() >= MIN_SHARE_PASSWORD_LEN => {
+            Some(hash_password(password)?)
+        }
+        (ShareType::Password, _) => {
+            return Err(AppError::FieldErrors(vec![FieldError::new(
+                "password",
+                format!("must be at least {} characters", MIN_SHARE_PASSWORD_LEN),
+            )]));
+        }
+        _ => None,
+    };
+
     let share_token = Uuid::new_v4().to_string();
 
     let share = sqlx::query_as::<_, FormShare>(
-        "INSERT INTO form_shares (form_id, share_type, share_token, expires_at)
-         VALUES ($1, $2, $3, $4)
+        "INSERT INTO form_shares (form_id, share_type, share_token, expires_at, password_hash)
+         VALUES ($1, $2, $3, $4, $5)
          RETURNING *"
     )
     .bind(form_id)
-    .bind(&payload.share_type)
+    .bind(payload.share_type.as_str())
     .bind(&share_token)
     .bind(payload.expires_at)
+    .bind(&password_hash)
     .fetch_one(&pool)
     .await
     .map_err(AppError::DatabaseError)?;
@@ -335,3 +900,101 @@ async fn get_share(
 
     Ok(Json(share))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    pub emails: Vec<String>,
+}
+
+async fn fetch_invite_share(
+    pool: &PgPool,
+    user_id: Uuid,
+    form_id: Uuid,
+    share_id: Uuid,
+) -> Result<FormShare, AppError> {
+    let share = sqlx::query_as::<_, FormShare>(
+        "SELECT s.* FROM form_shares s
+         JOIN forms f ON f.id = s.form_id
+         WHERE s.id = $1 AND s.form_id = $2 AND f.user_id = $3"
+    )
+    .bind(share_id)
+    .bind(form_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Share not found".to_string()))?;
+
+    if share.share_type != ShareType::Invite {
+        return Err(AppError::ValidationError("Only invite shares have invitees".to_string()));
+    }
+
+    Ok(share)
+}
+
+// Issues a personal token per address; addresses already invited keep theirs
+async fn create_invites(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, share_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<InviteRequest>,
+) -> Result<Json<Vec<ShareInvite>>, AppError> {
+    let share = fetch_invite_share(&pool, auth_user.user_id, form_id, share_id).await?;
+
+    if payload.emails.is_empty() {
+        return Err(AppError::FieldErrors(vec![FieldError::new("emails", "must not be empty")]));
+    }
+
+    let errors: Vec<FieldError> = payload
+        .emails
+        .iter()
+        .enumerate()
+        .filter(|(_, email)| !validator::validate_email(email.trim()))
+        .map(|(i, _)| FieldError::new(format!("emails[{}]", i), "must be a valid email address"))
+        .collect();
+    if !errors.is_empty() {
+        return Err(AppError::FieldErrors(errors));
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let mut invites = Vec::with_capacity(payload.emails.len());
+
+    for email in &payload.emails {
+        let invite = sqlx::query_as::<_, ShareInvite>(
+            "INSERT INTO share_invites (share_id, email, token)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (share_id, email) DO UPDATE SET email = EXCLUDED.email
+             RETURNING *"
+        )
+        .bind(share.id)
+        .bind(email.trim().to_lowercase())
+        .bind(Uuid::new_v4().to_string())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        invites.push(invite);
+    }
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(invites))
+}
+
+async fn list_invites(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, share_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<ShareInvite>>, AppError> {
+    let share = fetch_invite_share(&pool, auth_user.user_id, form_id, share_id).await?;
+
+    let invites = sqlx::query_as::<_, ShareInvite>(
+        "SELECT * FROM share_invites WHERE share_id = $1 ORDER BY created_at, email"
+    )
+    .bind(share.id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(invites))
+}
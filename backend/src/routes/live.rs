@@ -0,0 +1,170 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    routing::{get, post},
+    Router,
+    Extension,
+    Json,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    auth::AuthUser,
+    live::{self, LiveFeed, Subscription},
+};
+
+const WS_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/responses/live/ticket", post(create_ticket))
+        .route("/forms/:id/responses/live", get(live_events))
+        .route("/forms/:id/responses/live/ws", get(live_socket))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiveQuery {
+    // For clients that can't set headers, like EventSource and browser
+    // WebSockets: a single-use ticket from the ticket endpoint, so no
+    // long-lived token ends up in URLs and logs
+    ticket: Option<String>,
+    // Resume after this event; EventSource sends the Last-Event-ID header
+    // instead
+    last_event_id: Option<i64>,
+}
+
+async fn check_owner(pool: &PgPool, form_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    sqlx::query("SELECT id FROM forms WHERE id = $1 AND user_id = $2")
+        .bind(form_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+    Ok(())
+}
+
+// A ticket that opens the form's live feed once, within a minute
+async fn create_ticket(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<JsonValue>, AppError> {
+    check_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let (ticket, expires_at) = live::issue_ticket(&pool, form_id, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(json!({ "ticket": ticket, "expires_at": expires_at })))
+}
+
+// Checks the caller owns the form, then subscribes to its feed
+async fn subscribe(
+    auth_user: Option<AuthUser>,
+    pool: PgPool,
+    feed: &LiveFeed,
+    form_id: Uuid,
+    query: &LiveQuery,
+    last_event_id: Option<i64>,
+) -> Result<Subscription, AppError> {
+    let user_id = match (auth_user, &query.ticket) {
+        (Some(auth_user), _) => auth_user.user_id,
+        (None, Some(ticket)) => live::redeem_ticket(&pool, form_id, ticket)
+            .await
+            .map_err(AppError::DatabaseError)?
+            .ok_or(AppError::AuthError)?,
+        (None, None) => return Err(AppError::AuthError),
+    };
+
+    check_owner(&pool, form_id, user_id).await?;
+
+    feed.subscribe(pool, form_id, last_event_id.or(query.last_event_id))
+        .await
+        .map_err(AppError::DatabaseError)
+}
+
+// Server-Sent Events, one per created or deleted response, with the event id
+// as the SSE id so EventSource resumes where it left off
+async fn live_events(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Extension(feed): Extension<LiveFeed>,
+    Path(form_id): Path<Uuid>,
+    Query(query): Query<LiveQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let subscription = subscribe(auth_user, pool, &feed, form_id, &query, last_event_id).await?;
+
+    let events = subscription.into_stream().map(|event| {
+        Ok(Event::default()
+            .id(event.id.to_string())
+            .event(event.event.clone())
+            .json_data(&event)
+            .unwrap_or_default())
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// The same events as JSON text messages
+async fn live_socket(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Extension(feed): Extension<LiveFeed>,
+    Path(form_id): Path<Uuid>,
+    Query(query): Query<LiveQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let subscription = subscribe(auth_user, pool, &feed, form_id, &query, None).await?;
+
+    Ok(upgrade.on_upgrade(move |socket| send_events(socket, subscription)))
+}
+
+async fn send_events(mut socket: WebSocket, subscription: Subscription) {
+    let events = subscription.into_stream();
+    futures::pin_mut!(events);
+    let mut ping = tokio::time::interval(WS_PING_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let Ok(text) = serde_json::to_string(&event) else { continue };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+            }
+            // Nothing is expected from the client besides closing
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    let _ = socket.close().await;
+}
@@ -1,4 +1,17 @@
 pub mod auth;
 pub mod forms;
+pub mod templates;
+pub mod definitions;
+pub mod sections;
+pub mod versions;
+pub mod quiz;
+pub mod analytics;
+pub mod sheets;
+pub mod webhooks;
+pub mod notifications;
+pub mod live;
 pub mod responses;
+pub mod drafts;
+pub mod shared;
+pub mod uploads;
 pub mod payments;
@@ -0,0 +1,216 @@
+use axum::{
+    routing::get,
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{ConfirmationEmail, NotificationSettings, UpdateConfirmationEmail, UpdateNotificationSettings},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    db::fetch_elements,
+    elements::ElementType,
+    notifications::{unknown_placeholders, DAILY, FREQUENCIES, HOURLY},
+};
+
+const MAX_SUBJECT_LENGTH: usize = 255;
+const MAX_BODY_LENGTH: usize = 10_000;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/notifications", get(get_settings).put(update_settings))
+        .route(
+            "/forms/:id/confirmation",
+            get(get_confirmation).put(update_confirmation).delete(delete_confirmation),
+        )
+}
+
+async fn get_settings(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<NotificationSettings>, AppError> {
+    sqlx::query_as::<_, NotificationSettings>(
+        "SELECT f.id AS form_id, COALESCE(n.frequency, 'off') AS frequency, n.updated_at
+         FROM forms f
+         LEFT JOIN form_notifications n ON n.form_id = f.id
+         WHERE f.id = $1 AND f.user_id = $2"
+    )
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .map(Json)
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))
+}
+
+// A digest switched on now covers responses from now on, not the form's
+// whole history; switching digests off forgets what the next one would have
+// reported
+async fn update_settings(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<UpdateNotificationSettings>,
+) -> Result<Json<NotificationSettings>, AppError> {
+    if !FREQUENCIES.contains(&payload.frequency.as_str()) {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "frequency",
+            format!("must be one of {}", FREQUENCIES.join(", ")),
+        )]));
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    let settings = sqlx::query_as::<_, NotificationSettings>(
+        "INSERT INTO form_notifications (form_id, frequency)
+         SELECT id, $2 FROM forms WHERE id = $1 AND user_id = $3
+         ON CONFLICT (form_id) DO UPDATE
+         SET frequency = $2,
+             digest_sent_at = CASE WHEN form_notifications.frequency = $2
+                                   THEN form_notifications.digest_sent_at ELSE NOW() END,
+             updated_at = NOW()
+         RETURNING form_id, frequency, updated_at"
+    )
+    .bind(form_id)
+    .bind(&payload.frequency)
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    if payload.frequency != HOURLY && payload.frequency != DAILY {
+        sqlx::query("UPDATE form_responses SET digest_pending = FALSE WHERE form_id = $1 AND digest_pending")
+            .bind(form_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?;
+    }
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(settings))
+}
+
+fn no_confirmation() -> AppError {
+    AppError::NotFound("This form has no confirmation email".to_string())
+}
+
+async fn get_confirmation(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<ConfirmationEmail>, AppError> {
+    sqlx::query_as::<_, ConfirmationEmail>(
+        "SELECT c.* FROM confirmation_emails c
+         JOIN forms f ON f.id = c.form_id
+         WHERE c.form_id = $1 AND f.user_id = $2"
+    )
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .map(Json)
+    .ok_or_else(no_confirmation)
+}
+
+async fn update_confirmation(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<UpdateConfirmationEmail>,
+) -> Result<Json<ConfirmationEmail>, AppError> {
+    sqlx::query("SELECT id FROM forms WHERE id = $1 AND user_id = $2")
+        .bind(form_id)
+        .bind(auth_user.user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let elements = fetch_elements(&pool, form_id).await.map_err(AppError::DatabaseError)?;
+    let mut errors = Vec::new();
+
+    let email_questions: Vec<Uuid> = elements
+        .iter()
+        .filter(|element| element.element_type == ElementType::Email)
+        .map(|element| element.id)
+        .collect();
+    match payload.email_element_id {
+        Some(id) if !email_questions.contains(&id) => {
+            errors.push(FieldError::new("email_element_id", "must be one of the form's email questions"));
+        }
+        None if email_questions.is_empty() => {
+            errors.push(FieldError::new("email_element_id", "the form has no email question to send to"));
+        }
+        _ => {}
+    }
+
+    let element_ids: Vec<Uuid> = elements.iter().map(|element| element.id).collect();
+    for (field, template, max_length) in [
+        ("subject", &payload.subject, MAX_SUBJECT_LENGTH),
+        ("body", &payload.body, MAX_BODY_LENGTH),
+    ] {
+        if template.trim().is_empty() {
+            errors.push(FieldError::new(field, "must not be empty"));
+        } else if template.chars().count() > max_length {
+            errors.push(FieldError::new(field, format!("must be at most {} characters", max_length)));
+        }
+        for name in unknown_placeholders(template, &element_ids) {
+            errors.push(FieldError::new(field, format!("has an unknown placeholder {{{{{}}}}}", name)));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::FieldErrors(errors));
+    }
+
+    let confirmation = sqlx::query_as::<_, ConfirmationEmail>(
+        "INSERT INTO confirmation_emails (form_id, is_enabled, email_element_id, subject, body, include_answers)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (form_id) DO UPDATE
+         SET is_enabled = $2, email_element_id = $3, subject = $4, body = $5, include_answers = $6,
+             updated_at = NOW()
+         RETURNING *"
+    )
+    .bind(form_id)
+    .bind(payload.is_enabled.unwrap_or(true))
+    .bind(payload.email_element_id)
+    .bind(payload.subject.trim())
+    .bind(&payload.body)
+    .bind(payload.include_answers.unwrap_or(true))
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(confirmation))
+}
+
+async fn delete_confirmation(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "DELETE FROM confirmation_emails
+         WHERE form_id = $1 AND EXISTS (SELECT 1 FROM forms WHERE id = $1 AND user_id = $2)"
+    )
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .execute(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(no_confirmation());
+    }
+
+    Ok(())
+}
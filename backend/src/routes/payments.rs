@@ -8,7 +8,6 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use reqwest::Client;
-use uuid::Uuid;
 
 use crate::{
     models::Subscription,
@@ -41,7 +40,6 @@ struct PaystackInitializeResponse {
 #[derive(Debug, Deserialize)]
 struct PaystackInitializeData {
     authorization_url: String,
-    access_code: String,
     reference: String,
 }
 
@@ -118,7 +116,6 @@ struct PaystackVerifyResponse {
 #[derive(Debug, Deserialize)]
 struct PaystackVerifyData {
     status: String,
-    reference: String,
 }
 
 async fn verify_payment(
@@ -192,43 +189,39 @@ async fn payment_webhook(
     Extension(pool): Extension<PgPool>,
     Json(payload): Json<PaystackWebhookEvent>,
 ) -> Result<(), AppError> {
-    match payload.event.as_str() {
-        "charge.success" => {
-            if payload.data.status == "success" {
-                let subscription = sqlx::query!(
-                    "SELECT user_id FROM subscriptions WHERE paystack_reference = $1",
-                    payload.data.reference
-                )
-                .fetch_optional(&pool)
-                .await
-                .map_err(AppError::DatabaseError)?;
-
-                if let Some(subscription) = subscription {
-                    // Update subscription status
-                    sqlx::query!(
-                        "UPDATE subscriptions 
-                         SET status = 'active',
-                             end_date = NOW() + INTERVAL '1 year',
-                             updated_at = NOW()
-                         WHERE paystack_reference = $1",
-                        payload.data.reference
-                    )
-                    .execute(&pool)
-                    .await
-                    .map_err(AppError::DatabaseError)?;
-
-                    // Update user's subscription plan
-                    sqlx::query!(
-                        "UPDATE users SET subscription_plan = 'unlimited' WHERE id = $1",
-                        subscription.user_id
-                    )
-                    .execute(&pool)
-                    .await
-                    .map_err(AppError::DatabaseError)?;
-                }
-            }
+    // Other events are ignored
+    if payload.event == "charge.success" && payload.data.status == "success" {
+        let subscription = sqlx::query!(
+            "SELECT user_id FROM subscriptions WHERE paystack_reference = $1",
+            payload.data.reference
+        )
+        .fetch_optional(&pool)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if let Some(subscription) = subscription {
+            // Update subscription status
+            sqlx::query!(
+                "UPDATE subscriptions 
+                 SET status = 'active',
+                     end_date = NOW() + INTERVAL '1 year',
+                     updated_at = NOW()
+                 WHERE paystack_reference = $1",
+                payload.data.reference
+            )
+            .execute(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+
+            // Update user's subscription plan
+            sqlx::query!(
+                "UPDATE users SET subscription_plan = 'unlimited' WHERE id = $1",
+                subscription.user_id
+            )
+            .execute(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
         }
-        _ => {} // Ignore other events
     }
 
     Ok(())
@@ -0,0 +1,104 @@
+use axum::{
+    routing::get,
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    auth::AuthUser,
+    versions::export_columns,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/quiz/stats", get(quiz_stats))
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct QuestionStats {
+    pub element_id: Uuid,
+    #[sqlx(default)]
+    pub question: String,
+    pub attempts: i64,
+    pub correct: i64,
+    pub correct_rate: f64,
+    pub average_points: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuizStats {
+    pub graded_responses: i64,
+    pub average_score: Option<f64>,
+    // Average of each response's score as a fraction of its maximum
+    pub average_percentage: Option<f64>,
+    pub questions: Vec<QuestionStats>,
+}
+
+async fn quiz_stats(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<QuizStats>, AppError> {
+    // Verify form ownership
+    sqlx::query!(
+        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
+        form_id,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let (graded_responses, average_score, average_percentage) = sqlx::query_as::<_, (i64, Option<f64>, Option<f64>)>(
+        "SELECT COUNT(*),
+                AVG(score)::FLOAT8,
+                AVG(score::FLOAT8 / NULLIF(max_score, 0)) * 100
+         FROM form_responses
+         WHERE form_id = $1 AND grading IS NOT NULL"
+    )
+    .bind(form_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let mut questions = sqlx::query_as::<_, QuestionStats>(
+        "SELECT (q->>'element_id')::UUID AS element_id,
+                COUNT(*) AS attempts,
+                COUNT(*) FILTER (WHERE (q->>'correct')::BOOLEAN) AS correct,
+                (COUNT(*) FILTER (WHERE (q->>'correct')::BOOLEAN))::FLOAT8 / COUNT(*) AS correct_rate,
+                AVG((q->>'points_awarded')::INTEGER)::FLOAT8 AS average_points
+         FROM form_responses r
+         CROSS JOIN LATERAL jsonb_array_elements(r.grading->'questions') q
+         WHERE r.form_id = $1 AND r.grading IS NOT NULL
+         GROUP BY 1"
+    )
+    .bind(form_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    // Label and order questions the same way exports do, including ones that
+    // have since been removed from the form
+    let columns = export_columns(&mut *pool.acquire().await.map_err(AppError::DatabaseError)?, form_id).await.map_err(AppError::DatabaseError)?;
+    let position = |id: Uuid| columns.iter().position(|column| column.element_id == id).unwrap_or(usize::MAX);
+    for stats in &mut questions {
+        if let Some(column) = columns.iter().find(|column| column.element_id == stats.element_id) {
+            stats.question = column.label.clone();
+        }
+    }
+    questions.sort_by_key(|stats| position(stats.element_id));
+
+    Ok(Json(QuizStats {
+        graded_responses,
+        average_score,
+        average_percentage,
+        questions,
+    }))
+}
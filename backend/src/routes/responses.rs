@@ -1,19 +1,35 @@
 use axum::{
-    routing::{get, post},
+    routing::{get, post, delete},
     Router,
     Json,
     Extension,
     extract::{Path, Query},
+    http::header,
+    response::{IntoResponse, Response},
 };
-use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{types::{Json as SqlJson, JsonValue}, PgConnection, PgPool};
 use uuid::Uuid;
-use csv::Writer;
+use time::OffsetDateTime;
 
 use crate::{
-    models::{FormResponse, CreateFormResponse},
-    error::AppError,
+    models::{Form, FormElement, FormResponse, CreateFormResponse, SheetConnection},
+    error::{AppError, FieldError},
     auth::AuthUser,
+    client::ClientId,
+    elements::ElementType,
+    grading::grade_response,
+    live,
+    validation::validate_response,
+    export::{self, ExportFormat, ExportLayout, ExportWriter, XLSX_MAX_ROWS},
+    routes::uploads::attachment,
+    sheets::{self, Sheets},
+    storage::Storage,
+    uploads::delete_blobs,
+    notifications,
+    versions::published_layout,
+    webhooks,
 };
 
 pub fn router() -> Router {
@@ -21,19 +37,22 @@ pub fn router() -> Router {
         .route("/forms/:id/responses", post(create_response))
         .route("/forms/:id/responses", get(list_responses))
         .route("/forms/:id/responses/export", get(export_responses))
+        .route("/forms/:id/responses/:response_id", delete(delete_response))
 }
 
 async fn create_response(
     auth_user: Option<AuthUser>,
+    client: ClientId,
     Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
     Path(form_id): Path<Uuid>,
     Json(payload): Json<CreateFormResponse>,
 ) -> Result<Json<FormResponse>, AppError> {
     // Verify form exists and allows responses
-    let form = sqlx::query!(
-        "SELECT allow_anonymous FROM forms WHERE id = $1",
-        form_id
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1"
     )
+    .bind(form_id)
     .fetch_optional(&pool)
     .await
     .map_err(AppError::DatabaseError)?
@@ -42,20 +61,300 @@ async fn create_response(
     if !form.allow_anonymous && auth_user.is_none() {
         return Err(AppError::AuthorizationError);
     }
+    let respondent_id = auth_user.map(|u| u.user_id);
+    check_direct_access(&pool, &form, respondent_id).await?;
+
+    let response = submit_response(&pool, &sheets, &form, respondent_id, &client, Vec::new(), payload).await?;
+
+    Ok(Json(response))
+}
+
+/// Refuses answers sent straight to `form` by id when its owner has put it
+/// behind a password or invite-only share link, since those must go through
+/// the link, even if the form is also public. Only the owner is let through.
+pub async fn check_direct_access(pool: &PgPool, form: &Form, user_id: Option<Uuid>) -> Result<(), AppError> {
+    if user_id == Some(form.user_id) {
+        return Ok(());
+    }
+
+    let protected: bool = sqlx::query_scalar(
+        "SELECT EXISTS (
+             SELECT 1 FROM form_shares WHERE form_id = $1 AND share_type IN ('password', 'invite')
+         )"
+    )
+    .bind(form.id)
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if protected {
+        return Err(AppError::AuthorizationError);
+    }
+
+    Ok(())
+}
+
+/// Something a response uses up when it's stored.
+pub enum Claim {
+    /// A personal invite, which takes one response
+    Invite(Uuid),
+    /// A saved draft being finalised
+    Draft(Uuid),
+}
+
+/// Validates, grades and stores a response to `form`, enforcing its schedule
+/// and response limits. Callers decide who may respond; a response made
+/// through an invite or from a draft uses them up in the same transaction.
+pub async fn submit_response(
+    pool: &PgPool,
+    sheets: &Sheets,
+    form: &Form,
+    respondent_id: Option<Uuid>,
+    client: &ClientId,
+    claims: Vec<Claim>,
+    payload: CreateFormResponse,
+) -> Result<FormResponse, AppError> {
+    let form_id = form.id;
+
+    if let Some(reason) = form.closed_reason(OffsetDateTime::now_utc()) {
+        return Err(AppError::FormClosed(reason));
+    }
+
+    // Responses are checked against, and pinned to, the published version
+    let layout = published_layout(pool, form_id).await.map_err(AppError::DatabaseError)?;
+
+    if let Some(pages) = &payload.pages_reached {
+        if pages.iter().any(|page| !layout.sections.iter().any(|section| section.id == *page)) {
+            return Err(AppError::FieldErrors(vec![FieldError::new(
+                "pages_reached",
+                "contains sections that are not part of this form",
+            )]));
+        }
+    }
+
+    let response_data = validate_response(&layout.elements, &payload.response_data)?;
+
+    let grade = form.is_quiz.then(|| grade_response(&layout.elements, &response_data));
+
+    let client_key = match respondent_id {
+        Some(_) => None,
+        None => client.key_for(form_id),
+    };
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
 
-    let response = sqlx::query_as::<_, FormResponse>(
-        "INSERT INTO form_responses (form_id, respondent_id, response_data)
-         VALUES ($1, $2, $3)
+    // Holding the form row until commit serialises submissions, so the counts
+    // below can't race with a concurrent insert
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 FOR UPDATE"
+    )
+    .bind(form_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    // The form may have been closed or archived since the caller loaded it
+    if let Some(reason) = form.closed_reason(OffsetDateTime::now_utc()) {
+        return Err(AppError::FormClosed(reason));
+    }
+
+    check_response_limits(&mut tx, &form, respondent_id, client_key.as_deref()).await?;
+
+    let mut response = sqlx::query_as::<_, FormResponse>(
+        "INSERT INTO form_responses
+         (form_id, respondent_id, response_data, pages_reached, version_id, client_key, score, max_score, grading)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
          RETURNING *"
     )
     .bind(form_id)
-    .bind(auth_user.map(|u| u.user_id))
-    .bind(&payload.response_data)
-    .fetch_one(&pool)
+    .bind(respondent_id)
+    .bind(&response_data)
+    .bind(&payload.pages_reached)
+    .bind(layout.version_id)
+    .bind(&client_key)
+    .bind(grade.as_ref().map(|grade| grade.score))
+    .bind(grade.as_ref().map(|grade| grade.max_score))
+    .bind(grade.as_ref().map(SqlJson))
+    .fetch_one(&mut *tx)
     .await
     .map_err(AppError::DatabaseError)?;
 
-    Ok(Json(response))
+    attach_uploads(&mut tx, form_id, response.id, &layout.elements, &response_data).await?;
+
+    for claim in claims {
+        match claim {
+            Claim::Invite(invite_id) => {
+                let claimed = sqlx::query(
+                    "UPDATE share_invites SET response_id = $1, responded_at = NOW()
+                     WHERE id = $2 AND responded_at IS NULL"
+                )
+                .bind(response.id)
+                .bind(invite_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::DatabaseError)?;
+
+                // Someone else used the invite first; dropping the transaction
+                // discards this response
+                if claimed.rows_affected() == 0 {
+                    return Err(AppError::FormClosed("This invite has already been used".to_string()));
+                }
+            }
+            Claim::Draft(draft_id) => {
+                // Gone if the draft was submitted twice at once
+                let claimed = sqlx::query("DELETE FROM response_drafts WHERE id = $1")
+                    .bind(draft_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(AppError::DatabaseError)?;
+
+                if claimed.rows_affected() == 0 {
+                    return Err(AppError::NotFound("Draft not found".to_string()));
+                }
+            }
+        }
+    }
+
+    webhooks::enqueue(&mut tx, form_id, webhooks::RESPONSE_CREATED, json!({ "response": &response }))
+        .await
+        .map_err(AppError::DatabaseError)?;
+    // The response that fills the form closes it
+    if let Some(max) = form.max_responses {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM form_responses WHERE form_id = $1")
+            .bind(form_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        if count >= max as i64 {
+            webhooks::form_closed(&mut tx, &form)
+                .await
+                .map_err(AppError::DatabaseError)?;
+        }
+    }
+    notifications::response_created(&mut tx, &form, response.id).await?;
+    notifications::confirm_response(&mut tx, &form, &layout.elements, &response).await?;
+    live::publish(&mut tx, form_id, webhooks::RESPONSE_CREATED, json!({ "response": &response }))
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    sheets::sync_in_background(pool.clone(), sheets.clone(), form.id);
+
+    // Owners always see the grade; respondents only when the quiz reveals it
+    if !form.show_score_immediately {
+        response.score = None;
+        response.max_score = None;
+        response.grading = None;
+    }
+
+    Ok(response)
+}
+
+// Claims the uploads listed in file upload answers for this response. Each
+// file must have been uploaded to the same question and not used before.
+async fn attach_uploads(
+    conn: &mut PgConnection,
+    form_id: Uuid,
+    response_id: Uuid,
+    elements: &[FormElement],
+    response_data: &JsonValue,
+) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    for element in elements.iter().filter(|e| e.element_type == ElementType::FileUpload) {
+        let Some(files) = response_data.get(element.id.to_string()).and_then(|v| v.as_array()) else {
+            continue;
+        };
+        let file_ids: Vec<Uuid> = files
+            .iter()
+            .filter_map(|file| file.as_str().and_then(|id| Uuid::parse_str(id).ok()))
+            .collect();
+
+        let attached = sqlx::query(
+            "UPDATE file_uploads SET response_id = $1
+             WHERE id = ANY($2) AND form_id = $3 AND element_id = $4 AND response_id IS NULL"
+        )
+        .bind(response_id)
+        .bind(&file_ids)
+        .bind(form_id)
+        .bind(element.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if attached.rows_affected() != file_ids.len() as u64 {
+            errors.push(FieldError::new(
+                element.id.to_string(),
+                "contains files that were not uploaded for this question",
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+async fn check_response_limits(
+    conn: &mut PgConnection,
+    form: &Form,
+    respondent_id: Option<Uuid>,
+    client_key: Option<&str>,
+) -> Result<(), AppError> {
+    if let Some(max) = form.max_responses {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM form_responses WHERE form_id = $1"
+        )
+        .bind(form.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if count >= max as i64 {
+            return Err(AppError::ResponseLimitReached(
+                "This form has reached its maximum number of responses".to_string(),
+            ));
+        }
+    }
+
+    if let (Some(max), Some(respondent_id)) = (form.max_responses_per_respondent, respondent_id) {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM form_responses WHERE form_id = $1 AND respondent_id = $2"
+        )
+        .bind(form.id)
+        .bind(respondent_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if count >= max as i64 {
+            return Err(AppError::ResponseLimitReached(
+                "You have already submitted the maximum number of responses to this form".to_string(),
+            ));
+        }
+    }
+
+    if let (Some(max), Some(client_key)) = (form.max_responses_per_client, client_key) {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM form_responses WHERE form_id = $1 AND client_key = $2"
+        )
+        .bind(form.id)
+        .bind(client_key)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        if count >= max as i64 {
+            return Err(AppError::ResponseLimitReached(
+                "This device has already submitted the maximum number of responses to this form".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 async fn list_responses(
@@ -64,7 +363,7 @@ async fn list_responses(
     Path(form_id): Path<Uuid>,
 ) -> Result<Json<Vec<FormResponse>>, AppError> {
     // Verify form ownership
-    let form = sqlx::query!(
+    sqlx::query!(
         "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
         form_id,
         auth_user.user_id
@@ -75,9 +374,9 @@ async fn list_responses(
     .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
 
     let responses = sqlx::query_as::<_, FormResponse>(
-        "SELECT * FROM form_responses WHERE form_id = $1 ORDER BY created_at DESC",
-        form_id
+        "SELECT * FROM form_responses WHERE form_id = $1 ORDER BY created_at DESC"
     )
+    .bind(form_id)
     .fetch_all(&pool)
     .await
     .map_err(AppError::DatabaseError)?;
@@ -85,9 +384,76 @@ async fn list_responses(
     Ok(Json(responses))
 }
 
+async fn delete_response(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Extension(storage): Extension<Storage>,
+    Path((form_id, response_id)): Path<(Uuid, Uuid)>,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    // Upload rows go with the response; their files are removed after commit
+    let storage_keys: Vec<String> = sqlx::query_scalar(
+        "SELECT storage_key FROM file_uploads WHERE response_id = $1"
+    )
+    .bind(response_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let result = sqlx::query(
+        "DELETE FROM form_responses
+         WHERE id = $1 AND form_id = $2
+         AND EXISTS (SELECT 1 FROM forms WHERE id = $2 AND user_id = $3)"
+    )
+    .bind(response_id)
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Response not found".to_string()));
+    }
+
+    webhooks::enqueue(&mut tx, form_id, webhooks::RESPONSE_DELETED, json!({ "form_id": form_id, "response_id": response_id }))
+        .await
+        .map_err(AppError::DatabaseError)?;
+    live::publish(&mut tx, form_id, webhooks::RESPONSE_DELETED, json!({ "form_id": form_id, "response_id": response_id }))
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    delete_blobs(&storage, &storage_keys).await;
+
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ExportQuery {
-    format: String, // "csv" or "sheets"
+    format: String, // "csv", "xlsx", "ndjson", "parquet" or "sheets"
+    // CSV only: a single character, or "tab"
+    delimiter: Option<String>,
+    // "rfc3339", "unix" or a custom format; see `DateFormat::parse`
+    date_format: Option<String>,
+    #[serde(default)]
+    include_removed: bool,
+}
+
+fn csv_delimiter(delimiter: Option<&str>) -> Result<u8, AppError> {
+    match delimiter {
+        None => Ok(b','),
+        Some("tab") | Some("\t") => Ok(b'\t'),
+        Some(other) => match other.as_bytes() {
+            [byte] if (byte.is_ascii_punctuation() && *byte != b'"') || matches!(byte, b' ' | b'\t') => Ok(*byte),
+            _ => Err(AppError::FieldErrors(vec![FieldError::new(
+                "delimiter",
+                "must be a single punctuation character, a space or \"tab\"",
+            )])),
+        },
+    }
 }
 
 async fn export_responses(
@@ -95,51 +461,68 @@ async fn export_responses(
     Extension(pool): Extension<PgPool>,
     Path(form_id): Path<Uuid>,
     Query(query): Query<ExportQuery>,
-) -> Result<String, AppError> {
+) -> Result<Response, AppError> {
     // Verify form ownership
-    let form = sqlx::query!(
-        "SELECT title FROM forms WHERE id = $1 AND user_id = $2",
-        form_id,
-        auth_user.user_id
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 AND user_id = $2"
     )
+    .bind(form_id)
+    .bind(auth_user.user_id)
     .fetch_optional(&pool)
     .await
     .map_err(AppError::DatabaseError)?
     .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
 
-    let responses = sqlx::query_as::<_, FormResponse>(
-        "SELECT * FROM form_responses WHERE form_id = $1 ORDER BY created_at",
-        form_id
-    )
-    .fetch_all(&pool)
-    .await
-    .map_err(AppError::DatabaseError)?;
+    // Sheets are kept up to date as responses arrive, and caught up with
+    // POST /forms/:id/sheets/sync; an export only reports the linked sheet
+    if query.format == "sheets" {
+        let connection = sqlx::query_as::<_, SheetConnection>("SELECT * FROM sheet_connections WHERE form_id = $1")
+            .bind(form.id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?
+            .ok_or_else(|| AppError::NotFound("This form is not linked to a spreadsheet".to_string()))?;
+        return Ok(Json(connection).into_response());
+    }
+    let format = ExportFormat::parse(&query.format)
+        .ok_or_else(|| AppError::ValidationError("Invalid export format".to_string()))?;
 
-    match query.format.as_str() {
-        "csv" => {
-            let mut wtr = Writer::from_writer(vec![]);
-            
-            // Write headers
-            wtr.write_record(&["Response ID", "Created At", "Response Data"])?;
-
-            // Write data
-            for response in responses {
-                wtr.write_record(&[
-                    response.id.to_string(),
-                    response.created_at.to_string(),
-                    response.response_data.to_string(),
-                ])?;
-            }
+    let delimiter = csv_delimiter(query.delimiter.as_deref())?;
+    let layout = ExportLayout::load(
+        &mut *pool.acquire().await.map_err(AppError::DatabaseError)?,
+        form.id,
+        form.is_quiz,
+        query.date_format.as_deref(),
+        query.include_removed,
+    )
+    .await?;
 
-            let csv_data = String::from_utf8(wtr.into_inner()?)
-                .map_err(|_| AppError::InternalError)?;
-            
-            Ok(csv_data)
-        }
-        "sheets" => {
-            // TODO: Implement Google Sheets export
-            Err(AppError::ValidationError("Sheets export not implemented yet".to_string()))
+    // Catch this before the body starts, while there's still a status to set
+    if format == ExportFormat::Xlsx {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM form_responses WHERE form_id = $1")
+            .bind(form.id)
+            .fetch_one(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        if count >= XLSX_MAX_ROWS as i64 {
+            return Err(AppError::ValidationError(
+                "Too many responses for an Excel sheet; export as CSV instead".to_string(),
+            ));
         }
-        _ => Err(AppError::ValidationError("Invalid export format".to_string())),
     }
+
+    // An xlsx writer starts out by creating a temporary file
+    let writer = tokio::task::spawn_blocking(move || ExportWriter::new(format, layout, delimiter))
+        .await
+        .unwrap_or(Err(AppError::InternalError))?;
+
+    let file_name = format!("{}-responses.{}", form.title, format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, attachment(&file_name)),
+        ],
+        export::stream(pool, form.id, writer),
+    )
+        .into_response())
 }
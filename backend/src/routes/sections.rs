@@ -0,0 +1,271 @@
+use axum::{
+    routing::{get, post, put, delete},
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{FormSection, CreateFormSection, FormElement},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    db::fetch_sections,
+    routes::forms::{check_layout, lock_owned_form},
+    versions::published_layout,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/sections", post(create_section))
+        .route("/forms/:id/sections", get(list_sections))
+        .route("/forms/:id/sections", put(reorder_sections))
+        .route("/forms/:id/sections/:section_id", put(update_section))
+        .route("/forms/:id/sections/:section_id", delete(delete_section))
+        .route("/forms/:id/elements/:element_id/move", post(move_element))
+}
+
+pub fn validate_section(payload: &CreateFormSection) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if payload.title.trim().is_empty() {
+        errors.push(FieldError::new("title", "must not be empty"));
+    }
+    if payload.order_index < 0 {
+        errors.push(FieldError::new("order_index", "must not be negative"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+async fn create_section(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<CreateFormSection>,
+) -> Result<Json<FormSection>, AppError> {
+    // Verify form ownership
+    sqlx::query!(
+        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
+        form_id,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    validate_section(&payload)?;
+
+    let section = sqlx::query_as::<_, FormSection>(
+        "INSERT INTO form_sections (form_id, title, description, order_index)
+         VALUES ($1, $2, $3, $4)
+         RETURNING *"
+    )
+    .bind(form_id)
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(payload.order_index)
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(section))
+}
+
+async fn list_sections(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<Vec<FormSection>>, AppError> {
+    // Verify form access
+    let form = sqlx::query!(
+        "SELECT user_id FROM forms WHERE id = $1 AND (user_id = $2 OR is_public = true)",
+        form_id,
+        auth_user.user_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let sections = if form.user_id == auth_user.user_id {
+        fetch_sections(&pool, form_id).await.map_err(AppError::DatabaseError)?
+    } else {
+        published_layout(&pool, form_id).await.map_err(AppError::DatabaseError)?.sections
+    };
+
+    Ok(Json(sections))
+}
+
+async fn update_section(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, section_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<CreateFormSection>,
+) -> Result<Json<FormSection>, AppError> {
+    validate_section(&payload)?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    let section = sqlx::query_as::<_, FormSection>(
+        "UPDATE form_sections
+         SET title = $1, description = $2, order_index = $3, updated_at = NOW()
+         WHERE id = $4 AND form_id = $5
+         RETURNING *"
+    )
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(payload.order_index)
+    .bind(section_id)
+    .bind(form_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Section not found".to_string()))?;
+
+    // A new position moves every element in the section
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(section))
+}
+
+async fn delete_section(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, section_id)): Path<(Uuid, Uuid)>,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    // Elements in the section are kept and become unsectioned, which moves
+    // them to the front of the form
+    let result = sqlx::query("DELETE FROM form_sections WHERE id = $1 AND form_id = $2")
+        .bind(section_id)
+        .bind(form_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Section not found".to_string()));
+    }
+
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderSectionsRequest {
+    pub section_ids: Vec<Uuid>,
+}
+
+async fn reorder_sections(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<ReorderSectionsRequest>,
+) -> Result<Json<Vec<FormSection>>, AppError> {
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    // Lock the form so concurrent reorders cannot interleave
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    let sections = fetch_sections(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+
+    let mut requested = payload.section_ids.clone();
+    requested.sort();
+    requested.dedup();
+    let mut existing: Vec<Uuid> = sections.iter().map(|section| section.id).collect();
+    existing.sort();
+    if requested.len() != payload.section_ids.len() || requested != existing {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "section_ids",
+            "must list every section of the form exactly once",
+        )]));
+    }
+
+    for (index, section_id) in payload.section_ids.iter().enumerate() {
+        sqlx::query("UPDATE form_sections SET order_index = $1, updated_at = NOW() WHERE id = $2")
+            .bind(index as i32)
+            .bind(section_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?;
+    }
+
+    check_layout(&mut tx, form_id).await?;
+    let sections = fetch_sections(&mut *tx, form_id).await.map_err(AppError::DatabaseError)?;
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(sections))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveElementRequest {
+    // None moves the element out of any section
+    pub section_id: Option<Uuid>,
+    pub order_index: i32,
+}
+
+async fn move_element(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, element_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<MoveElementRequest>,
+) -> Result<Json<FormElement>, AppError> {
+    if payload.order_index < 0 {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "order_index",
+            "must not be negative",
+        )]));
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    lock_owned_form(&mut tx, form_id, auth_user.user_id).await?;
+
+    if let Some(section_id) = payload.section_id {
+        sqlx::query("SELECT id FROM form_sections WHERE id = $1 AND form_id = $2")
+            .bind(section_id)
+            .bind(form_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(AppError::DatabaseError)?
+            .ok_or_else(|| AppError::FieldErrors(vec![FieldError::new(
+                "section_id",
+                "section is not part of this form",
+            )]))?;
+    }
+
+    let element = sqlx::query_as::<_, FormElement>(
+        "UPDATE form_elements
+         SET section_id = $1, order_index = $2, updated_at = NOW()
+         WHERE id = $3 AND form_id = $4
+         RETURNING *"
+    )
+    .bind(payload.section_id)
+    .bind(payload.order_index)
+    .bind(element_id)
+    .bind(form_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Element not found".to_string()))?;
+
+    check_layout(&mut tx, form_id).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(element))
+}
@@ -0,0 +1,349 @@
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::{DefaultBodyLimit, Multipart, Path},
+    http::HeaderMap,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        Form, FormStatus, FormResponse, CreateFormResponse, FormShare, ShareInvite, ShareType, UploadedFile,
+        RespondentForm, RespondentSection, RespondentElement,
+    },
+    error::AppError,
+    auth::{verify_password, AuthUser},
+    client::ClientId,
+    config::{MAX_SHARE_PASSWORD_ATTEMPTS, MAX_UPLOAD_BYTES, SHARE_PASSWORD_LOCKOUT_SECS},
+    db::FORM_WITH_REMAINING,
+    routes::{responses::{submit_response, Claim}, uploads::store_upload},
+    sheets::Sheets,
+    storage::Storage,
+    versions::published_layout,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/shared/:token", get(get_shared_form))
+        .route("/shared/:token/responses", post(create_shared_response))
+        .route("/shared/:token/elements/:element_id/uploads", post(upload_shared_file))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES + 64 * 1024))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SharedForm {
+    pub form: RespondentForm,
+    // Set when the form can be viewed but not answered right now
+    pub closed_reason: Option<String>,
+    pub sections: Vec<RespondentSection>,
+    pub elements: Vec<RespondentElement>,
+}
+
+/// The form behind a token, and the invite if the token was an invitee's.
+pub struct ResolvedShare {
+    pub invite: Option<ShareInvite>,
+    pub form: Form,
+}
+
+/// Checks a share link (its expiry, password or invite) and finds its form,
+/// which must be open to link holders.
+pub async fn resolve_share(pool: &PgPool, token: &str, password: Option<&str>) -> Result<ResolvedShare, AppError> {
+    let share = sqlx::query_as::<_, FormShare>(
+        "SELECT * FROM form_shares WHERE share_token = $1"
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    let (share, invite) = match share {
+        Some(share) => (share, None),
+        None => {
+            let invite = sqlx::query_as::<_, ShareInvite>(
+                "SELECT * FROM share_invites WHERE token = $1"
+            )
+            .bind(token)
+            .fetch_optional(pool)
+            .await
+            .map_err(AppError::DatabaseError)?
+            .ok_or_else(|| AppError::NotFound("Share link not found".to_string()))?;
+
+            let share = sqlx::query_as::<_, FormShare>(
+                "SELECT * FROM form_shares WHERE id = $1"
+            )
+            .bind(invite.share_id)
+            .fetch_one(pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+
+            (share, Some(invite))
+        }
+    };
+
+    // Only a password that was actually checked counts towards the lockout
+    if let Some(unlocked) = check_share(&share, invite.as_ref(), password, OffsetDateTime::now_utc())? {
+        record_password_attempt(pool, share.id, unlocked).await?;
+        if !unlocked {
+            return Err(AppError::SharePasswordRequired);
+        }
+    }
+
+    let form = sqlx::query_as::<_, Form>(
+        &format!("SELECT {} FROM forms WHERE id = $1", FORM_WITH_REMAINING)
+    )
+    .bind(share.form_id)
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    // Unpublished and archived forms stay private even to link holders
+    if matches!(form.status, FormStatus::Draft | FormStatus::Archived) {
+        let reason = form.closed_reason(OffsetDateTime::now_utc()).unwrap_or_default();
+        return Err(AppError::FormClosed(reason));
+    }
+
+    Ok(ResolvedShare { invite, form })
+}
+
+// Checks a share's expiry and whether the caller may use it. For password
+// shares, returns whether the given password was right.
+fn check_share(
+    share: &FormShare,
+    invite: Option<&ShareInvite>,
+    password: Option<&str>,
+    now: OffsetDateTime,
+) -> Result<Option<bool>, AppError> {
+    if share.expires_at.map(|expires_at| expires_at <= now).unwrap_or(false) {
+        return Err(AppError::FormClosed("This share link has expired".to_string()));
+    }
+
+    match share.share_type {
+        ShareType::Link => Ok(None),
+        ShareType::Password => {
+            let Some(password) = password else {
+                return Err(AppError::SharePasswordRequired);
+            };
+            if share.password_locked_until.map(|until| until > now).unwrap_or(false) {
+                return Err(AppError::TooManyAttempts(
+                    "Too many wrong passwords, try again later".to_string(),
+                ));
+            }
+
+            match share.password_hash.as_deref() {
+                Some(hash) => verify_password(password, hash).map(Some),
+                None => Ok(Some(false)),
+            }
+        }
+        ShareType::Invite => match invite {
+            None => Err(AppError::AuthorizationError),
+            Some(invite) if invite.responded_at.is_some() => {
+                Err(AppError::FormClosed("This invite has already been used".to_string()))
+            }
+            Some(_) => Ok(None),
+        },
+    }
+}
+
+// Counts wrong passwords against a share. Once past the limit every further
+// miss locks the share again, so guessing is held to one try per lockout.
+async fn record_password_attempt(pool: &PgPool, share_id: Uuid, unlocked: bool) -> Result<(), AppError> {
+    if unlocked {
+        sqlx::query("UPDATE form_shares SET failed_password_attempts = 0 WHERE id = $1 AND failed_password_attempts > 0")
+            .bind(share_id)
+            .execute(pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        return Ok(());
+    }
+
+    sqlx::query(
+        "UPDATE form_shares
+         SET failed_password_attempts = failed_password_attempts + 1,
+             password_locked_until = CASE
+                 WHEN failed_password_attempts + 1 >= $2 THEN NOW() + make_interval(secs => $3)
+                 ELSE password_locked_until
+             END
+         WHERE id = $1"
+    )
+    .bind(share_id)
+    .bind(MAX_SHARE_PASSWORD_ATTEMPTS)
+    .bind(SHARE_PASSWORD_LOCKOUT_SECS as f64)
+    .execute(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(())
+}
+
+pub fn share_password(headers: &HeaderMap) -> Option<&str> {
+    headers.get("x-share-password").and_then(|value| value.to_str().ok())
+}
+
+async fn get_shared_form(
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<SharedForm>, AppError> {
+    let ResolvedShare { form, .. } = resolve_share(&pool, &token, share_password(&headers)).await?;
+
+    let layout = published_layout(&pool, form.id).await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(SharedForm {
+        closed_reason: form.closed_reason(OffsetDateTime::now_utc()),
+        form: form.into(),
+        sections: layout.sections.into_iter().map(Into::into).collect(),
+        elements: layout.elements.into_iter().map(Into::into).collect(),
+    }))
+}
+
+// The link itself grants access, so no account is needed; signed-in
+// respondents are still recorded
+async fn create_shared_response(
+    auth_user: Option<AuthUser>,
+    client: ClientId,
+    Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateFormResponse>,
+) -> Result<Json<FormResponse>, AppError> {
+    let ResolvedShare { form, invite } = resolve_share(&pool, &token, share_password(&headers)).await?;
+
+    let response = submit_response(
+        &pool,
+        &sheets,
+        &form,
+        auth_user.map(|u| u.user_id),
+        &client,
+        invite.map(|invite| Claim::Invite(invite.id)).into_iter().collect(),
+        payload,
+    )
+    .await?;
+
+    Ok(Json(response))
+}
+
+async fn upload_shared_file(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Extension(storage): Extension<Storage>,
+    Path((token, element_id)): Path<(String, Uuid)>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Json<UploadedFile>, AppError> {
+    let ResolvedShare { form, .. } = resolve_share(&pool, &token, share_password(&headers)).await?;
+
+    let upload = store_upload(&pool, &storage, &form, element_id, auth_user.map(|u| u.user_id), multipart).await?;
+
+    Ok(Json(upload))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn share(share_type: ShareType) -> FormShare {
+        FormShare {
+            id: Uuid::new_v4(),
+            form_id: Uuid::new_v4(),
+            share_type,
+            share_token: Uuid::new_v4().to_string(),
+            password_hash: None,
+            password_locked_until: None,
+            expires_at: None,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    fn password_share(password: &str) -> FormShare {
+        FormShare {
+            // The lowest cost keeps the tests quick
+            password_hash: Some(bcrypt::hash(password, 4).unwrap()),
+            ..share(ShareType::Password)
+        }
+    }
+
+    fn invite(responded_at: Option<OffsetDateTime>) -> ShareInvite {
+        ShareInvite {
+            id: Uuid::new_v4(),
+            share_id: Uuid::new_v4(),
+            email: "someone@example.com".to_string(),
+            token: Uuid::new_v4().to_string(),
+            response_id: None,
+            responded_at,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn links_expire() {
+        let now = OffsetDateTime::now_utc();
+        let mut link = share(ShareType::Link);
+        assert!(matches!(check_share(&link, None, None, now), Ok(None)));
+
+        link.expires_at = Some(now + Duration::minutes(1));
+        assert!(matches!(check_share(&link, None, None, now), Ok(None)));
+
+        link.expires_at = Some(now);
+        assert!(matches!(check_share(&link, None, None, now), Err(AppError::FormClosed(_))));
+    }
+
+    #[test]
+    fn passwords_are_checked() {
+        let now = OffsetDateTime::now_utc();
+        let share = password_share("open sesame");
+
+        assert!(matches!(check_share(&share, None, None, now), Err(AppError::SharePasswordRequired)));
+        assert!(matches!(check_share(&share, None, Some("wrong"), now), Ok(Some(false))));
+        assert!(matches!(check_share(&share, None, Some("open sesame"), now), Ok(Some(true))));
+    }
+
+    #[test]
+    fn locked_shares_refuse_even_the_right_password() {
+        let now = OffsetDateTime::now_utc();
+        let mut share = password_share("open sesame");
+
+        share.password_locked_until = Some(now + Duration::minutes(5));
+        assert!(matches!(
+            check_share(&share, None, Some("open sesame"), now),
+            Err(AppError::TooManyAttempts(_))
+        ));
+
+        share.password_locked_until = Some(now - Duration::seconds(1));
+        assert!(matches!(check_share(&share, None, Some("open sesame"), now), Ok(Some(true))));
+    }
+
+    #[test]
+    fn expiry_is_checked_before_the_password() {
+        let now = OffsetDateTime::now_utc();
+        let share = FormShare {
+            expires_at: Some(now - Duration::days(1)),
+            ..password_share("open sesame")
+        };
+
+        assert!(matches!(
+            check_share(&share, None, Some("open sesame"), now),
+            Err(AppError::FormClosed(_))
+        ));
+    }
+
+    #[test]
+    fn invite_shares_need_an_unused_invite() {
+        let now = OffsetDateTime::now_utc();
+        let share = share(ShareType::Invite);
+
+        assert!(matches!(check_share(&share, None, None, now), Err(AppError::AuthorizationError)));
+        assert!(matches!(check_share(&share, Some(&invite(None)), None, now), Ok(None)));
+        assert!(matches!(
+            check_share(&share, Some(&invite(Some(now))), None, now),
+            Err(AppError::FormClosed(_))
+        ));
+    }
+}
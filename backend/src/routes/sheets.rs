@@ -0,0 +1,185 @@
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{ConnectSheet, Form, SheetConnection},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    sheets::{self, Sheets},
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/sheets", get(get_connection).put(connect_sheet).delete(disconnect_sheet))
+        .route("/forms/:id/sheets/sync", post(sync_sheet))
+}
+
+fn validate_connection(payload: &ConnectSheet) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if payload.spreadsheet_id.trim().is_empty() {
+        errors.push(FieldError::new("spreadsheet_id", "must not be empty"));
+    } else if payload.spreadsheet_id.len() > 255 {
+        errors.push(FieldError::new("spreadsheet_id", "must be at most 255 characters"));
+    }
+    if payload.sheet_name.trim().is_empty() {
+        errors.push(FieldError::new("sheet_name", "must not be empty"));
+    } else if payload.sheet_name.chars().count() > 100 {
+        errors.push(FieldError::new("sheet_name", "must be at most 100 characters"));
+    }
+    if payload.refresh_token.trim().is_empty() {
+        errors.push(FieldError::new("refresh_token", "must not be empty"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+async fn owned_form(pool: &PgPool, form_id: Uuid, user_id: Uuid) -> Result<Form, AppError> {
+    sqlx::query_as::<_, Form>("SELECT * FROM forms WHERE id = $1 AND user_id = $2")
+        .bind(form_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))
+}
+
+fn not_connected() -> AppError {
+    AppError::NotFound("This form is not linked to a spreadsheet".to_string())
+}
+
+async fn get_connection(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<SheetConnection>, AppError> {
+    sqlx::query_as::<_, SheetConnection>(
+        "SELECT c.* FROM sheet_connections c
+         JOIN forms f ON f.id = c.form_id
+         WHERE c.form_id = $1 AND f.user_id = $2"
+    )
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .map(Json)
+    .ok_or_else(not_connected)
+}
+
+// Links the form to a sheet, replacing any previous link, and writes the
+// header row straight away so bad credentials are reported here. Backfilled
+// responses follow in the background.
+async fn connect_sheet(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<ConnectSheet>,
+) -> Result<Json<SheetConnection>, AppError> {
+    validate_connection(&payload)?;
+    let form = owned_form(&pool, form_id, auth_user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+
+    sqlx::query(
+        "INSERT INTO sheet_connections (form_id, spreadsheet_id, sheet_name, refresh_token, with_score)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (form_id) DO UPDATE
+         SET spreadsheet_id = $2, sheet_name = $3, refresh_token = $4, with_score = $5,
+             columns = '[]', headers = '[]', last_synced_at = NULL, last_error = NULL, updated_at = NOW()"
+    )
+    .bind(form.id)
+    .bind(payload.spreadsheet_id.trim())
+    .bind(payload.sheet_name.trim())
+    .bind(payload.refresh_token.trim())
+    .bind(form.is_quiz)
+    .execute(&mut *tx)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    // A new sheet starts empty; only what arrives from now on is pending
+    sqlx::query("UPDATE form_responses SET sheet_synced_at = NOW() WHERE form_id = $1")
+        .bind(form.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    let connection = match sheets::sync(&pool, sheets.as_ref(), form.id).await {
+        Ok(connection) => connection.ok_or_else(not_connected)?,
+        Err(e) => {
+            sqlx::query("DELETE FROM sheet_connections WHERE form_id = $1")
+                .bind(form.id)
+                .execute(&pool)
+                .await
+                .map_err(AppError::DatabaseError)?;
+            return Err(e);
+        }
+    };
+
+    // Rows a sync still holds a lease on are left to it, so they aren't
+    // appended twice
+    if payload.backfill {
+        sqlx::query(
+            "UPDATE form_responses SET sheet_synced_at = NULL
+             WHERE form_id = $1 AND (sheet_claimed_until IS NULL OR sheet_claimed_until <= NOW())"
+        )
+            .bind(form.id)
+            .execute(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        sheets::sync_in_background(pool, sheets, form.id);
+    }
+
+    Ok(Json(connection))
+}
+
+async fn disconnect_sheet(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "DELETE FROM sheet_connections
+         WHERE form_id = $1 AND EXISTS (SELECT 1 FROM forms WHERE id = $1 AND user_id = $2)"
+    )
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .execute(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(not_connected());
+    }
+
+    Ok(())
+}
+
+// Sends anything the background syncs missed, e.g. while Google was down
+async fn sync_sheet(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Extension(sheets): Extension<Sheets>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<SheetConnection>, AppError> {
+    let form = owned_form(&pool, form_id, auth_user.user_id).await?;
+
+    sheets::sync(&pool, sheets.as_ref(), form.id)
+        .await?
+        .map(Json)
+        .ok_or_else(not_connected)
+}
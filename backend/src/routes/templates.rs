@@ -0,0 +1,159 @@
+use axum::{
+    routing::{get, post, delete},
+    Router,
+    Json,
+    Extension,
+    extract::Path,
+};
+use serde::Deserialize;
+use sqlx::{types::Json as SqlJson, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    models::{Form, FormTemplate, CreateFormTemplate},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    definitions::FormDefinition,
+    routes::forms::check_form_quota,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/templates", get(list_templates))
+        .route("/templates", post(create_template))
+        .route("/templates/:id", get(get_template))
+        .route("/templates/:id", delete(delete_template))
+        .route("/templates/:id/forms", post(create_form_from_template))
+}
+
+// Built-in templates first, then the user's own
+async fn list_templates(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<FormTemplate>>, AppError> {
+    let templates = sqlx::query_as::<_, FormTemplate>(
+        "SELECT * FROM form_templates
+         WHERE user_id IS NULL OR user_id = $1
+         ORDER BY user_id NULLS FIRST, name"
+    )
+    .bind(auth_user.user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(templates))
+}
+
+async fn fetch_template(pool: &PgPool, id: Uuid, user_id: Uuid) -> Result<FormTemplate, AppError> {
+    sqlx::query_as::<_, FormTemplate>(
+        "SELECT * FROM form_templates WHERE id = $1 AND (user_id IS NULL OR user_id = $2)"
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Template not found".to_string()))
+}
+
+async fn get_template(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FormTemplate>, AppError> {
+    let template = fetch_template(&pool, id, auth_user.user_id).await?;
+
+    Ok(Json(template))
+}
+
+// Saves a snapshot of one of the user's forms as a template
+async fn create_template(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<CreateFormTemplate>,
+) -> Result<Json<FormTemplate>, AppError> {
+    if payload.name.trim().is_empty() {
+        return Err(AppError::FieldErrors(vec![FieldError::new("name", "must not be empty")]));
+    }
+
+    let mut conn = pool.acquire().await.map_err(AppError::DatabaseError)?;
+
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1 AND user_id = $2"
+    )
+    .bind(payload.form_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    let definition = FormDefinition::load(&mut conn, &form).await.map_err(AppError::DatabaseError)?;
+
+    let template = sqlx::query_as::<_, FormTemplate>(
+        "INSERT INTO form_templates (user_id, name, description, category, definition)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING *"
+    )
+    .bind(auth_user.user_id)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(&payload.category)
+    .bind(SqlJson(&definition))
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(template))
+}
+
+async fn delete_template(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<(), AppError> {
+    // Built-in templates have no owner, so they can't be deleted here
+    let result = sqlx::query(
+        "DELETE FROM form_templates WHERE id = $1 AND user_id = $2"
+    )
+    .bind(id)
+    .bind(auth_user.user_id)
+    .execute(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Template not found".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UseTemplateRequest {
+    pub title: Option<String>,
+}
+
+async fn create_form_from_template(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<UseTemplateRequest>>,
+) -> Result<Json<Form>, AppError> {
+    let template = fetch_template(&pool, id, auth_user.user_id).await?;
+
+    let mut definition = template.definition.0;
+    if let Some(title) = payload.and_then(|Json(payload)| payload.title).filter(|title| !title.trim().is_empty()) {
+        definition.title = title;
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    check_form_quota(&mut tx, auth_user.user_id).await?;
+    let form = definition
+        .instantiate(&mut tx, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(form))
+}
@@ -0,0 +1,247 @@
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::{DefaultBodyLimit, Multipart, Path},
+    http::header,
+    response::IntoResponse,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{Form, UploadedFile},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    config::MAX_UPLOAD_BYTES,
+    elements::{ElementOptions, ElementType},
+    routes::responses::check_direct_access,
+    storage::{Storage, StorageError},
+    uploads::content_matches,
+    versions::published_layout,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/elements/:element_id/uploads", post(upload_file))
+        .route("/forms/:id/uploads/:upload_id", get(download_file))
+        // Leave room for the multipart framing around the file itself
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES + 64 * 1024))
+}
+
+async fn upload_file(
+    auth_user: Option<AuthUser>,
+    Extension(pool): Extension<PgPool>,
+    Extension(storage): Extension<Storage>,
+    Path((form_id, element_id)): Path<(Uuid, Uuid)>,
+    multipart: Multipart,
+) -> Result<Json<UploadedFile>, AppError> {
+    let form = sqlx::query_as::<_, Form>(
+        "SELECT * FROM forms WHERE id = $1"
+    )
+    .bind(form_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    if !form.allow_anonymous && auth_user.is_none() {
+        return Err(AppError::AuthorizationError);
+    }
+    let user_id = auth_user.map(|u| u.user_id);
+    check_direct_access(&pool, &form, user_id).await?;
+
+    let upload = store_upload(&pool, &storage, &form, element_id, user_id, multipart).await?;
+
+    Ok(Json(upload))
+}
+
+/// Stores the `file` field of `multipart` as an upload for a file upload
+/// question on the published form, enforcing the element's size and type
+/// limits. Callers decide who may upload.
+pub async fn store_upload(
+    pool: &PgPool,
+    storage: &Storage,
+    form: &Form,
+    element_id: Uuid,
+    uploaded_by: Option<Uuid>,
+    mut multipart: Multipart,
+) -> Result<UploadedFile, AppError> {
+    if let Some(reason) = form.closed_reason(time::OffsetDateTime::now_utc()) {
+        return Err(AppError::FormClosed(reason));
+    }
+
+    let layout = published_layout(pool, form.id).await.map_err(AppError::DatabaseError)?;
+    let element = layout
+        .elements
+        .iter()
+        .find(|element| element.id == element_id)
+        .ok_or_else(|| AppError::NotFound("Element not found".to_string()))?;
+
+    if element.element_type != ElementType::FileUpload {
+        return Err(AppError::ValidationError("This question does not accept files".to_string()));
+    }
+
+    let options = match ElementOptions::parse(element.element_type, element.options.as_ref()) {
+        Ok(ElementOptions::FileUpload(options)) => options,
+        _ => Default::default(),
+    };
+    let max_size = options
+        .max_size_bytes
+        .map(|max| (max as usize).min(MAX_UPLOAD_BYTES))
+        .unwrap_or(MAX_UPLOAD_BYTES);
+
+    let multipart_error = |e: axum::extract::multipart::MultipartError| AppError::ValidationError(e.to_string());
+
+    let mut field = loop {
+        match multipart.next_field().await.map_err(multipart_error)? {
+            Some(field) if field.name() == Some("file") => break field,
+            Some(_) => continue,
+            None => {
+                return Err(AppError::FieldErrors(vec![FieldError::new("file", "no file was uploaded")]));
+            }
+        }
+    };
+
+    let file_name = field
+        .file_name()
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .map(|name| name.chars().take(255).collect::<String>())
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "upload".to_string());
+    let content_type = field
+        .content_type()
+        .unwrap_or("application/octet-stream")
+        .to_ascii_lowercase();
+
+    if !mime_allowed(&options.allowed_mime_types, &content_type) {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "file",
+            format!("files of type {} are not accepted", content_type),
+        )]));
+    }
+
+    // Read chunk by chunk so oversized files are rejected without buffering
+    // them whole
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        if data.len() + chunk.len() > max_size {
+            return Err(AppError::FieldErrors(vec![FieldError::new(
+                "file",
+                format!("must be at most {} bytes", max_size),
+            )]));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    if data.is_empty() {
+        return Err(AppError::FieldErrors(vec![FieldError::new("file", "must not be empty")]));
+    }
+    // The declared type is what the allowed types were checked against
+    if !content_matches(&content_type, &data) {
+        return Err(AppError::FieldErrors(vec![FieldError::new(
+            "file",
+            format!("contents are not a valid {} file", content_type),
+        )]));
+    }
+
+    let id = Uuid::new_v4();
+    let storage_key = format!("forms/{}/{}", form.id, id);
+    let size_bytes = data.len() as i64;
+
+    storage.put(&storage_key, data.into()).await.map_err(storage_error)?;
+
+    let upload = sqlx::query_as::<_, UploadedFile>(
+        "INSERT INTO file_uploads (id, form_id, element_id, uploaded_by, storage_key, file_name, content_type, size_bytes)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         RETURNING *"
+    )
+    .bind(id)
+    .bind(form.id)
+    .bind(element_id)
+    .bind(uploaded_by)
+    .bind(&storage_key)
+    .bind(&file_name)
+    .bind(&content_type)
+    .bind(size_bytes)
+    .fetch_one(pool)
+    .await;
+
+    match upload {
+        Ok(upload) => Ok(upload),
+        Err(e) => {
+            // Don't leave a blob nothing points at
+            if let Err(delete_error) = storage.delete(&storage_key).await {
+                tracing::warn!("failed to remove orphaned upload {}: {}", storage_key, delete_error);
+            }
+            Err(AppError::DatabaseError(e))
+        }
+    }
+}
+
+// Patterns are exact types or `type/*`; no patterns accepts anything
+fn mime_allowed(allowed: &[String], content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+
+    allowed.is_empty()
+        || allowed.iter().any(|pattern| {
+            let pattern = pattern.trim().to_ascii_lowercase();
+            match pattern.strip_suffix("/*") {
+                Some(kind) => essence.split('/').next() == Some(kind),
+                None => pattern == essence,
+            }
+        })
+}
+
+fn storage_error(e: StorageError) -> AppError {
+    match e {
+        StorageError::NotFound => AppError::NotFound("File not found".to_string()),
+        StorageError::Backend(message) => {
+            tracing::error!("storage error: {}", message);
+            AppError::InternalError
+        }
+    }
+}
+
+async fn download_file(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Extension(storage): Extension<Storage>,
+    Path((form_id, upload_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    // Only the form's owner can read what respondents uploaded
+    let upload = sqlx::query_as::<_, UploadedFile>(
+        "SELECT u.* FROM file_uploads u
+         JOIN forms f ON f.id = u.form_id
+         WHERE u.id = $1 AND u.form_id = $2 AND f.user_id = $3"
+    )
+    .bind(upload_id)
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+
+    let data = storage.get(&upload.storage_key).await.map_err(storage_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, upload.content_type),
+            (header::CONTENT_DISPOSITION, attachment(&upload.file_name)),
+            // Browsers must not second-guess the type respondents gave
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        data,
+    ))
+}
+
+/// A `Content-Disposition` value offering `file_name` as a download.
+pub fn attachment(file_name: &str) -> String {
+    // Header values must be plain ASCII
+    let file_name: String = file_name
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    format!("attachment; filename=\"{}\"", file_name)
+}
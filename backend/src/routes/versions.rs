@@ -0,0 +1,163 @@
+use axum::{
+    routing::{get, post},
+    Router,
+    Json,
+    Extension,
+    extract::{Path, Query},
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{FormVersion, FormVersionSummary, FormSnapshot},
+    error::AppError,
+    auth::AuthUser,
+    db::{fetch_elements, fetch_sections},
+    versions::{create_version, diff_snapshots, restore_snapshot, SnapshotDiff},
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/versions", post(publish_version))
+        .route("/forms/:id/versions", get(list_versions))
+        .route("/forms/:id/versions/:version_id", get(get_version))
+        .route("/forms/:id/versions/:version_id/diff", get(diff_version))
+        .route("/forms/:id/versions/:version_id/rollback", post(rollback_version))
+}
+
+async fn verify_owner(pool: &PgPool, form_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    sqlx::query!(
+        "SELECT id FROM forms WHERE id = $1 AND user_id = $2",
+        form_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    Ok(())
+}
+
+async fn fetch_version(pool: &PgPool, form_id: Uuid, version_id: Uuid) -> Result<FormVersion, AppError> {
+    sqlx::query_as::<_, FormVersion>(
+        "SELECT * FROM form_versions WHERE id = $1 AND form_id = $2"
+    )
+    .bind(version_id)
+    .bind(form_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Version not found".to_string()))
+}
+
+async fn publish_version(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<FormVersion>, AppError> {
+    verify_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let version = create_version(&mut tx, form_id, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(version))
+}
+
+async fn list_versions(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<Vec<FormVersionSummary>>, AppError> {
+    verify_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let versions = sqlx::query_as::<_, FormVersionSummary>(
+        "SELECT id, version_number, created_by, created_at FROM form_versions
+         WHERE form_id = $1
+         ORDER BY version_number DESC"
+    )
+    .bind(form_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(versions))
+}
+
+async fn get_version(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, version_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<FormVersion>, AppError> {
+    verify_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let version = fetch_version(&pool, form_id, version_id).await?;
+
+    Ok(Json(version))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    // Version to compare against; defaults to the unpublished live form
+    against: Option<Uuid>,
+}
+
+async fn diff_version(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, version_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<SnapshotDiff>, AppError> {
+    verify_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let version = fetch_version(&pool, form_id, version_id).await?;
+
+    let other = match query.against {
+        Some(other_id) => fetch_version(&pool, form_id, other_id).await?.snapshot.0,
+        None => {
+            let form = sqlx::query_as::<_, (String, Option<String>)>(
+                "SELECT title, description FROM forms WHERE id = $1"
+            )
+            .bind(form_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+
+            FormSnapshot {
+                title: form.0,
+                description: form.1,
+                sections: fetch_sections(&pool, form_id).await.map_err(AppError::DatabaseError)?,
+                elements: fetch_elements(&pool, form_id).await.map_err(AppError::DatabaseError)?,
+            }
+        }
+    };
+
+    Ok(Json(diff_snapshots(&version.snapshot.0, &other)))
+}
+
+async fn rollback_version(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, version_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<FormVersion>, AppError> {
+    verify_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let version = fetch_version(&pool, form_id, version_id).await?;
+
+    // Restore the old layout and publish it again so new responses are
+    // pinned to a version that matches what respondents see
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    restore_snapshot(&mut tx, form_id, &version.snapshot.0)
+        .await
+        .map_err(AppError::DatabaseError)?;
+    let republished = create_version(&mut tx, form_id, auth_user.user_id)
+        .await
+        .map_err(AppError::DatabaseError)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    Ok(Json(republished))
+}
@@ -0,0 +1,273 @@
+use axum::{
+    routing::{get, post, put},
+    Router,
+    Json,
+    Extension,
+    extract::{Path, Query},
+};
+use reqwest::Url;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::{CreateWebhook, CreatedWebhook, Webhook, WebhookDelivery},
+    error::{AppError, FieldError},
+    auth::AuthUser,
+    webhooks::{check_destination, EVENTS},
+};
+
+const MAX_URL_LENGTH: usize = 2048;
+const MIN_SECRET_LENGTH: usize = 16;
+const DEFAULT_DELIVERY_PAGE: i64 = 50;
+const MAX_DELIVERY_PAGE: i64 = 200;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/forms/:id/webhooks", post(create_webhook).get(list_webhooks))
+        .route("/forms/:id/webhooks/:webhook_id", put(update_webhook).delete(delete_webhook))
+        .route("/forms/:id/webhooks/:webhook_id/deliveries", get(list_deliveries))
+        .route(
+            "/forms/:id/webhooks/:webhook_id/deliveries/:delivery_id/redeliver",
+            post(redeliver),
+        )
+}
+
+async fn validate_webhook(payload: &CreateWebhook) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    match Url::parse(&payload.url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {
+            if payload.url.len() > MAX_URL_LENGTH {
+                errors.push(FieldError::new("url", format!("must be at most {} characters", MAX_URL_LENGTH)));
+            } else if let Err(message) = check_destination(&url).await {
+                errors.push(FieldError::new("url", message));
+            }
+        }
+        _ => errors.push(FieldError::new("url", "must be an http or https URL")),
+    }
+
+    if payload.events.is_empty() {
+        errors.push(FieldError::new("events", "must list at least one event"));
+    }
+    for (index, event) in payload.events.iter().enumerate() {
+        if !EVENTS.contains(&event.as_str()) {
+            errors.push(FieldError::new(
+                format!("events[{}]", index),
+                format!("must be one of {}", EVENTS.join(", ")),
+            ));
+        }
+    }
+
+    if let Some(secret) = &payload.secret {
+        if secret.len() < MIN_SECRET_LENGTH || secret.len() > 255 {
+            errors.push(FieldError::new(
+                "secret",
+                format!("must be between {} and 255 characters", MIN_SECRET_LENGTH),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+fn new_secret() -> String {
+    format!("whsec_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn events(payload: &CreateWebhook) -> Vec<String> {
+    let mut events = payload.events.clone();
+    events.sort();
+    events.dedup();
+    events
+}
+
+async fn check_form_owner(pool: &PgPool, form_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    sqlx::query("SELECT id FROM forms WHERE id = $1 AND user_id = $2")
+        .bind(form_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::DatabaseError)?
+        .ok_or_else(|| AppError::NotFound("Form not found".to_string()))?;
+
+    Ok(())
+}
+
+async fn create_webhook(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<Json<CreatedWebhook>, AppError> {
+    validate_webhook(&payload).await?;
+    check_form_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "INSERT INTO webhooks (form_id, url, secret, events, is_active)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING *"
+    )
+    .bind(form_id)
+    .bind(&payload.url)
+    .bind(payload.secret.clone().unwrap_or_else(new_secret))
+    .bind(events(&payload))
+    .bind(payload.is_active.unwrap_or(true))
+    .fetch_one(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(CreatedWebhook {
+        secret: webhook.secret.clone(),
+        webhook,
+    }))
+}
+
+async fn list_webhooks(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path(form_id): Path<Uuid>,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    check_form_owner(&pool, form_id, auth_user.user_id).await?;
+
+    let webhooks = sqlx::query_as::<_, Webhook>(
+        "SELECT * FROM webhooks WHERE form_id = $1 ORDER BY created_at"
+    )
+    .bind(form_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(webhooks))
+}
+
+async fn update_webhook(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<Json<Webhook>, AppError> {
+    validate_webhook(&payload).await?;
+
+    let webhook = sqlx::query_as::<_, Webhook>(
+        "UPDATE webhooks
+         SET url = $3, secret = COALESCE($4, secret), events = $5, is_active = COALESCE($6, is_active),
+             updated_at = NOW()
+         WHERE id = $1 AND form_id = $2
+         AND EXISTS (SELECT 1 FROM forms WHERE id = $2 AND user_id = $7)
+         RETURNING *"
+    )
+    .bind(webhook_id)
+    .bind(form_id)
+    .bind(&payload.url)
+    .bind(&payload.secret)
+    .bind(events(&payload))
+    .bind(payload.is_active)
+    .bind(auth_user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))?;
+
+    Ok(Json(webhook))
+}
+
+async fn delete_webhook(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "DELETE FROM webhooks
+         WHERE id = $1 AND form_id = $2
+         AND EXISTS (SELECT 1 FROM forms WHERE id = $2 AND user_id = $3)"
+    )
+    .bind(webhook_id)
+    .bind(form_id)
+    .bind(auth_user.user_id)
+    .execute(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Webhook not found".to_string()));
+    }
+
+    Ok(())
+}
+
+async fn find_webhook(pool: &PgPool, form_id: Uuid, webhook_id: Uuid, user_id: Uuid) -> Result<Webhook, AppError> {
+    sqlx::query_as::<_, Webhook>(
+        "SELECT w.* FROM webhooks w
+         JOIN forms f ON f.id = w.form_id
+         WHERE w.id = $1 AND w.form_id = $2 AND f.user_id = $3"
+    )
+    .bind(webhook_id)
+    .bind(form_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryQuery {
+    limit: Option<i64>,
+    // "pending", "succeeded" or "failed"
+    status: Option<String>,
+}
+
+// Newest first
+async fn list_deliveries(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, AppError> {
+    let webhook = find_webhook(&pool, form_id, webhook_id, auth_user.user_id).await?;
+
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        "SELECT * FROM webhook_deliveries
+         WHERE webhook_id = $1 AND ($2::text IS NULL OR status = $2)
+         ORDER BY created_at DESC, id
+         LIMIT $3"
+    )
+    .bind(webhook.id)
+    .bind(&query.status)
+    .bind(query.limit.unwrap_or(DEFAULT_DELIVERY_PAGE).clamp(1, MAX_DELIVERY_PAGE))
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    Ok(Json(deliveries))
+}
+
+// Queues the same event again as a new delivery, leaving the original in
+// the log
+async fn redeliver(
+    auth_user: AuthUser,
+    Extension(pool): Extension<PgPool>,
+    Path((form_id, webhook_id, delivery_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<Json<WebhookDelivery>, AppError> {
+    let webhook = find_webhook(&pool, form_id, webhook_id, auth_user.user_id).await?;
+
+    let delivery = sqlx::query_as::<_, WebhookDelivery>(
+        "INSERT INTO webhook_deliveries (webhook_id, event, payload)
+         SELECT webhook_id, event, payload FROM webhook_deliveries
+         WHERE id = $1 AND webhook_id = $2
+         RETURNING *"
+    )
+    .bind(delivery_id)
+    .bind(webhook.id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    .ok_or_else(|| AppError::NotFound("Delivery not found".to_string()))?;
+
+    Ok(Json(delivery))
+}
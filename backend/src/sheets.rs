@@ -0,0 +1,301 @@
+use std::{env, sync::Arc, time::Duration};
+
+use axum::async_trait;
+use reqwest::{Client, RequestBuilder, Url};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use sqlx::{types::Json, PgPool};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    export::{DateFormat, ExportLayout, ExportRow, EXPORT_ROWS},
+    models::SheetConnection,
+};
+
+// Responses appended per request to the sheet
+const SYNC_BATCH_ROWS: i64 = 500;
+const SHEETS_TIMEOUT: Duration = Duration::from_secs(30);
+// How long a batch being appended is hidden from other syncs
+const CLAIM_LEASE_SECONDS: f64 = 120.0;
+
+#[derive(Error, Debug)]
+pub enum SheetsError {
+    #[error("could not reach Google: {0}")]
+    Request(String),
+
+    #[error("Google rejected the request ({status}): {message}")]
+    Rejected { status: u16, message: String },
+}
+
+/// Somewhere a form's responses are written out as spreadsheet rows.
+#[async_trait]
+pub trait SpreadsheetSink: Send + Sync {
+    /// Writes the first row of the sheet, replacing what's there.
+    async fn write_header(&self, sheet: &SheetConnection, headers: &[String]) -> Result<(), SheetsError>;
+    /// Adds rows below the last one with data.
+    async fn append_rows(&self, sheet: &SheetConnection, rows: Vec<Vec<JsonValue>>) -> Result<(), SheetsError>;
+}
+
+pub type Sheets = Arc<dyn SpreadsheetSink>;
+
+/// Google Sheets, using the OAuth client in `GOOGLE_CLIENT_ID` and
+/// `GOOGLE_CLIENT_SECRET`. `GOOGLE_SHEETS_API_URL` and
+/// `GOOGLE_OAUTH_TOKEN_URL` point it somewhere other than Google.
+pub fn sheets_from_env() -> anyhow::Result<Sheets> {
+    Ok(Arc::new(GoogleSheets {
+        http: Client::builder().timeout(SHEETS_TIMEOUT).build()?,
+        api_url: Url::parse(
+            &env::var("GOOGLE_SHEETS_API_URL").unwrap_or_else(|_| "https://sheets.googleapis.com".into()),
+        )?,
+        token_url: env::var("GOOGLE_OAUTH_TOKEN_URL")
+            .unwrap_or_else(|_| "https://oauth2.googleapis.com/token".into()),
+        client_id: env::var("GOOGLE_CLIENT_ID").unwrap_or_default(),
+        client_secret: env::var("GOOGLE_CLIENT_SECRET").unwrap_or_default(),
+    }))
+}
+
+pub struct GoogleSheets {
+    http: Client,
+    api_url: Url,
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+impl GoogleSheets {
+    async fn access_token(&self, sheet: &SheetConnection) -> Result<String, SheetsError> {
+        let request = self.http.post(&self.token_url).form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", sheet.refresh_token.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ]);
+        let token: AccessToken = send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| SheetsError::Request(e.to_string()))?;
+        Ok(token.access_token)
+    }
+
+    // `.../v4/spreadsheets/{id}/values/{range}{suffix}`, for a range of the
+    // connection's sheet starting at `cell`
+    fn values_url(&self, sheet: &SheetConnection, cell: &str, suffix: &str) -> Url {
+        let range = format!("'{}'!{}{}", sheet.sheet_name.replace('\'', "''"), cell, suffix);
+        let mut url = self.api_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments
+                .pop_if_empty()
+                .extend(["v4", "spreadsheets", &sheet.spreadsheet_id, "values", &range]);
+        }
+        url
+    }
+}
+
+#[async_trait]
+impl SpreadsheetSink for GoogleSheets {
+    async fn write_header(&self, sheet: &SheetConnection, headers: &[String]) -> Result<(), SheetsError> {
+        let token = self.access_token(sheet).await?;
+        let request = self
+            .http
+            .put(self.values_url(sheet, "A1", ""))
+            .bearer_auth(token)
+            .query(&[("valueInputOption", "RAW")])
+            .json(&json!({ "values": [headers] }));
+        send(request).await.map(|_| ())
+    }
+
+    async fn append_rows(&self, sheet: &SheetConnection, rows: Vec<Vec<JsonValue>>) -> Result<(), SheetsError> {
+        let token = self.access_token(sheet).await?;
+        // RAW so answers like "=1+1" stay text rather than becoming formulas
+        let request = self
+            .http
+            .post(self.values_url(sheet, "A1", ":append"))
+            .bearer_auth(token)
+            .query(&[("valueInputOption", "RAW"), ("insertDataOption", "INSERT_ROWS")])
+            .json(&json!({ "values": rows }));
+        send(request).await.map(|_| ())
+    }
+}
+
+async fn send(request: RequestBuilder) -> Result<reqwest::Response, SheetsError> {
+    let response = request.send().await.map_err(|e| SheetsError::Request(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    // The Sheets API and the token endpoint report errors differently
+    let body: JsonValue = response.json().await.unwrap_or_default();
+    let message = body["error"]["message"]
+        .as_str()
+        .or_else(|| body["error_description"].as_str())
+        .or_else(|| body["error"].as_str())
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or("unknown error"))
+        .to_string();
+
+    Err(SheetsError::Rejected { status: status.as_u16(), message })
+}
+
+/// Appends the form's responses that aren't in its sheet yet, oldest first,
+/// and brings the header up to date. Questions added since the sheet was
+/// linked get new columns on the end. Returns `None` if the form has no
+/// sheet.
+pub async fn sync(pool: &PgPool, sheets: &dyn SpreadsheetSink, form_id: Uuid) -> Result<Option<SheetConnection>, AppError> {
+    let Some(mut connection) = sqlx::query_as::<_, SheetConnection>(
+        "SELECT * FROM sheet_connections WHERE form_id = $1"
+    )
+    .bind(form_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?
+    else {
+        return Ok(None);
+    };
+
+    // Failures on our side abandon the sync; the sheet's are recorded on the
+    // connection
+    let result = append_pending(pool, sheets, &mut connection).await?;
+
+    let connection = sqlx::query_as::<_, SheetConnection>(
+        "UPDATE sheet_connections
+         SET last_synced_at = CASE WHEN $2::text IS NULL THEN NOW() ELSE last_synced_at END,
+             last_error = $2, updated_at = NOW()
+         WHERE form_id = $1
+         RETURNING *"
+    )
+    .bind(form_id)
+    .bind(result.as_ref().err().map(|e| e.to_string()))
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::DatabaseError)?;
+
+    // The sheet may have been unlinked while we were at it
+    match connection {
+        Some(connection) => result.map(|()| Some(connection)).map_err(sheets_error),
+        None => Ok(None),
+    }
+}
+
+/// Syncs the form's sheet, if it has one, without holding up the caller.
+pub fn sync_in_background(pool: PgPool, sheets: Sheets, form_id: Uuid) {
+    tokio::spawn(async move {
+        if let Err(e) = sync(&pool, sheets.as_ref(), form_id).await {
+            tracing::warn!("sheet sync for form {} failed: {}", form_id, e);
+        }
+    });
+}
+
+pub fn sheets_error(e: SheetsError) -> AppError {
+    AppError::ValidationError(format!("Could not update the spreadsheet: {}", e))
+}
+
+// Nothing is locked or left open while Google is called: each batch is
+// claimed first, so concurrent syncs never append a response twice
+async fn append_pending(
+    pool: &PgPool,
+    sheets: &dyn SpreadsheetSink,
+    connection: &mut SheetConnection,
+) -> Result<Result<(), SheetsError>, AppError> {
+    let mut layout = ExportLayout::load(
+        &mut *pool.acquire().await.map_err(AppError::DatabaseError)?,
+        connection.form_id,
+        connection.with_score,
+        None,
+        true,
+    )
+    .await?;
+
+    // Existing columns keep their place; questions the sheet hasn't seen go
+    // on the end, unless they were already removed
+    let mut columns = std::mem::take(&mut layout.columns);
+    for id in connection.columns.iter() {
+        if let Some(index) = columns.iter().position(|column| column.element_id == *id) {
+            layout.columns.push(columns.remove(index));
+        }
+    }
+    layout.columns.extend(columns.into_iter().filter(|column| !column.removed));
+
+    let headers: Vec<String> = layout.fields().into_iter().map(|(header, _)| header).collect();
+    if headers != connection.headers.0 {
+        if let Err(e) = sheets.write_header(connection, &headers).await {
+            return Ok(Err(e));
+        }
+        let columns: Vec<Uuid> = layout.columns.iter().map(|column| column.element_id).collect();
+        sqlx::query("UPDATE sheet_connections SET columns = $2, headers = $3 WHERE form_id = $1")
+            .bind(connection.form_id)
+            .bind(Json(&columns))
+            .bind(Json(&headers))
+            .execute(pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        connection.columns.0 = columns;
+        connection.headers.0 = headers;
+    }
+
+    loop {
+        let pending: Vec<Uuid> = sqlx::query_scalar(
+            "WITH due AS (
+                 SELECT id FROM form_responses
+                 WHERE form_id = $1 AND sheet_synced_at IS NULL
+                   AND (sheet_claimed_until IS NULL OR sheet_claimed_until <= NOW())
+                 ORDER BY created_at, id
+                 LIMIT $2
+                 FOR UPDATE SKIP LOCKED
+             )
+             UPDATE form_responses r
+             SET sheet_claimed_until = NOW() + make_interval(secs => $3)
+             FROM due
+             WHERE r.id = due.id
+             RETURNING r.id"
+        )
+        .bind(connection.form_id)
+        .bind(SYNC_BATCH_ROWS)
+        .bind(CLAIM_LEASE_SECONDS)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::DatabaseError)?;
+        if pending.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let rows = sqlx::query_as::<_, ExportRow>(EXPORT_ROWS)
+            .bind(connection.form_id)
+            .bind(&pending)
+            .fetch_all(pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        let values = rows
+            .iter()
+            .map(|row| {
+                layout
+                    .values(row)
+                    .iter()
+                    .map(|value| value.to_json(&DateFormat::Rfc3339))
+                    .collect()
+            })
+            .collect();
+        let appended = sheets.append_rows(connection, values).await;
+
+        // A failed batch is given back for the next sync to retry
+        let query = match appended {
+            Ok(()) => "UPDATE form_responses SET sheet_synced_at = NOW(), sheet_claimed_until = NULL WHERE id = ANY($1)",
+            Err(_) => "UPDATE form_responses SET sheet_claimed_until = NULL WHERE id = ANY($1)",
+        };
+        sqlx::query(query)
+            .bind(&pending)
+            .execute(pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        if let Err(e) = appended {
+            return Ok(Err(e));
+        }
+    }
+}
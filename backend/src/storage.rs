@@ -0,0 +1,149 @@
+use std::{env, path::PathBuf, sync::Arc};
+
+use axum::async_trait;
+use bytes::Bytes;
+use object_store::{aws::AmazonS3Builder, path::Path as ObjectPath, ObjectStore};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("blob not found")]
+    NotFound,
+
+    #[error("storage error: {0}")]
+    Backend(String),
+}
+
+/// Where uploaded files live. Keys are `/`-separated relative paths.
+#[async_trait]
+pub trait BlobStorage: Send + Sync {
+    async fn put(&self, key: &str, data: Bytes) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+pub type Storage = Arc<dyn BlobStorage>;
+
+/// Picks a backend from the environment: `STORAGE_BACKEND=s3` uses the
+/// `S3_*` settings, anything else stores files under `UPLOAD_DIR`.
+pub fn storage_from_env() -> anyhow::Result<Storage> {
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("s3") => Ok(Arc::new(S3Storage::from_env()?)),
+        _ => Ok(Arc::new(LocalStorage::new(
+            env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".into()),
+        ))),
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        // Keys are generated by us, but never let one escape the root
+        if key.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(StorageError::Backend(format!("invalid key `{}`", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStorage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| StorageError::Backend(e.to_string()))?;
+        }
+        tokio::fs::write(&path, &data)
+            .await
+            .map_err(|e| StorageError::Backend(e.to_string()))
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Bytes::from(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(StorageError::NotFound),
+            Err(e) => Err(StorageError::Backend(e.to_string())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(StorageError::Backend(e.to_string())),
+        }
+    }
+}
+
+/// Any S3-compatible service; set `S3_ENDPOINT` for MinIO and the like.
+pub struct S3Storage {
+    store: object_store::aws::AmazonS3,
+}
+
+impl S3Storage {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(env::var("S3_BUCKET").map_err(|_| anyhow::anyhow!("S3_BUCKET must be set"))?)
+            .with_region(env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".into()));
+
+        if let Ok(key) = env::var("S3_ACCESS_KEY_ID") {
+            builder = builder.with_access_key_id(key);
+        }
+        if let Ok(secret) = env::var("S3_SECRET_ACCESS_KEY") {
+            builder = builder.with_secret_access_key(secret);
+        }
+        if let Ok(endpoint) = env::var("S3_ENDPOINT") {
+            // Self-hosted services usually don't do virtual-hosted buckets
+            builder = builder
+                .with_endpoint(endpoint.clone())
+                .with_virtual_hosted_style_request(false)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+
+        Ok(Self { store: builder.build()? })
+    }
+}
+
+fn object_error(e: object_store::Error) -> StorageError {
+    match e {
+        object_store::Error::NotFound { .. } => StorageError::NotFound,
+        e => StorageError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl BlobStorage for S3Storage {
+    async fn put(&self, key: &str, data: Bytes) -> Result<(), StorageError> {
+        self.store
+            .put(&ObjectPath::from(key), data.into())
+            .await
+            .map(|_| ())
+            .map_err(object_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        self.store
+            .get(&ObjectPath::from(key))
+            .await
+            .map_err(object_error)?
+            .bytes()
+            .await
+            .map_err(object_error)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match self.store.delete(&ObjectPath::from(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(object_error(e)),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::storage::Storage;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Uploads not used by a response or a saved draft are kept this long
+const UNATTACHED_UPLOAD_HOURS: i32 = 24;
+
+// Leading bytes of formats we can recognise, and the types that may be
+// declared for them
+const SIGNATURES: &[(&[u8], usize, &[&str])] = &[
+    (b"\x89PNG\r\n\x1a\n", 0, &["image/png"]),
+    (b"\xff\xd8\xff", 0, &["image/jpeg", "image/jpg", "image/pjpeg"]),
+    (b"GIF87a", 0, &["image/gif"]),
+    (b"GIF89a", 0, &["image/gif"]),
+    (b"WEBP", 8, &["image/webp"]),
+    (b"WAVE", 8, &["audio/wav", "audio/wave", "audio/x-wav"]),
+    (b"II*\x00", 0, &["image/tiff"]),
+    (b"MM\x00*", 0, &["image/tiff"]),
+    (b"%PDF-", 0, &["application/pdf"]),
+    (b"ftyp", 4, &["video/mp4", "audio/mp4", "video/quicktime", "image/heic", "image/heif", "image/avif"]),
+    (b"OggS", 0, &["audio/ogg", "video/ogg", "application/ogg"]),
+    (b"ID3", 0, &["audio/mpeg", "audio/mp3"]),
+    (b"\xff\xfb", 0, &["audio/mpeg", "audio/mp3"]),
+    (b"\x1a\x45\xdf\xa3", 0, &["video/webm", "audio/webm", "video/x-matroska"]),
+    (b"\x1f\x8b", 0, &["application/gzip", "application/x-gzip"]),
+    (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", 0, &[
+        "application/msword",
+        "application/vnd.ms-excel",
+        "application/vnd.ms-powerpoint",
+    ]),
+    (b"PK\x03\x04", 0, &[
+        "application/zip",
+        "application/x-zip-compressed",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "application/vnd.oasis.opendocument.text",
+        "application/vnd.oasis.opendocument.spreadsheet",
+        "application/vnd.oasis.opendocument.presentation",
+        "application/epub+zip",
+    ]),
+];
+
+// Declared types whose contents are checked as text rather than by signature
+fn is_text_type(essence: &str) -> bool {
+    essence.starts_with("text/")
+        || matches!(essence, "application/json" | "application/xml" | "image/svg+xml")
+}
+
+/// Whether `data` looks like what `content_type` claims. Types we know the
+/// signature of must start with it and text types must be UTF-8 text;
+/// anything else, including `application/octet-stream`, is taken as is.
+pub fn content_matches(content_type: &str, data: &[u8]) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+
+    let signed = |(magic, offset, _): &&(&[u8], usize, &[&str])| {
+        data.get(*offset..offset + magic.len()) == Some(*magic)
+    };
+    if let Some((_, _, types)) = SIGNATURES.iter().find(signed) {
+        return types.contains(&essence) || essence == "application/octet-stream";
+    }
+
+    if is_text_type(essence) {
+        return std::str::from_utf8(data).map(|text| !text.contains('\0')).unwrap_or(false);
+    }
+
+    !SIGNATURES.iter().any(|(_, _, types)| types.contains(&essence))
+}
+
+/// Deletes uploads no response or draft uses, and their files, for as long
+/// as the server runs.
+pub fn spawn_cleanup(pool: PgPool, storage: Storage) {
+    tokio::spawn(async move {
+        loop {
+            // A draft names its files in the answer to their question
+            let deleted = sqlx::query_scalar::<_, String>(
+                "DELETE FROM file_uploads u
+                 WHERE u.response_id IS NULL
+                 AND u.created_at < NOW() - make_interval(hours => $1)
+                 AND NOT EXISTS (
+                     SELECT 1 FROM response_drafts d
+                     WHERE d.form_id = u.form_id AND d.response_data -> u.element_id::text ? u.id::text
+                 )
+                 RETURNING u.storage_key"
+            )
+            .bind(UNATTACHED_UPLOAD_HOURS)
+            .fetch_all(&pool)
+            .await;
+
+            match deleted {
+                Ok(keys) => {
+                    if !keys.is_empty() {
+                        tracing::info!("deleting {} unused uploads", keys.len());
+                    }
+                    delete_blobs(&storage, &keys).await;
+                }
+                Err(e) => tracing::error!("deleting unused uploads failed: {}", e),
+            }
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
+
+/// Removes stored files whose upload rows are already gone. Failures are
+/// only logged; the rows can't be brought back.
+pub async fn delete_blobs(storage: &Storage, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = storage.delete(key).await {
+            tracing::warn!("failed to remove upload {}: {}", key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+
+    #[test]
+    fn known_signature_must_match_declared_type() {
+        assert!(content_matches("image/png", PNG));
+        assert!(!content_matches("image/jpeg", PNG));
+        assert!(!content_matches("application/pdf", b"<html><script>alert(1)</script>"));
+    }
+
+    #[test]
+    fn declared_type_with_signature_requires_it() {
+        assert!(!content_matches("image/png", b"not really a png"));
+    }
+
+    #[test]
+    fn office_documents_are_zip_containers() {
+        let docx = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        assert!(content_matches(docx, b"PK\x03\x04rest"));
+        assert!(!content_matches(docx, b"%PDF-1.7"));
+    }
+
+    #[test]
+    fn offset_signatures_are_checked() {
+        assert!(content_matches("image/webp", b"RIFF\x00\x00\x00\x00WEBPVP8 "));
+        assert!(content_matches("video/mp4", b"\x00\x00\x00\x18ftypmp42"));
+    }
+
+    #[test]
+    fn text_types_must_be_text() {
+        assert!(content_matches("text/csv; charset=utf-8", b"a,b\n1,2\n"));
+        assert!(!content_matches("text/plain", b"\x00\x01\x02"));
+        assert!(!content_matches("text/plain", PNG));
+    }
+
+    #[test]
+    fn unknown_types_are_taken_as_declared() {
+        assert!(content_matches("application/octet-stream", b"\x00\x01\x02"));
+        assert!(content_matches("application/octet-stream", PNG));
+        assert!(content_matches("application/x-custom", b"\x00\x01\x02"));
+    }
+}
@@ -0,0 +1,378 @@
+use serde_json::Map;
+use sqlx::types::JsonValue;
+use uuid::Uuid;
+
+use crate::{
+    elements::{parse_date, parse_time, ElementOptions, ElementType},
+    error::{AppError, FieldError},
+    logic::visible_elements,
+    models::FormElement,
+};
+
+/// Checks `response_data` against the form's elements (in form order) and
+/// returns the answers to store. Answers are keyed by element id; errors use
+/// the element id (or the offending key) as the field name. Elements hidden by
+/// conditional logic, or on pages their answers skip past, are not enforced
+/// and their answers are dropped.
+pub fn validate_response(elements: &[FormElement], data: &JsonValue) -> Result<JsonValue, AppError> {
+    let answers = data.as_object().ok_or_else(|| {
+        AppError::FieldErrors(vec![FieldError::new("response_data", "must be an object keyed by element id")])
+    })?;
+
+    let mut errors = Vec::new();
+    let mut cleaned = Map::new();
+
+    for key in answers.keys() {
+        let known = Uuid::parse_str(key)
+            .map(|id| elements.iter().any(|element| element.id == id))
+            .unwrap_or(false);
+        if !known {
+            errors.push(FieldError::new(key.as_str(), "unknown question"));
+        }
+    }
+
+    for element in shown_elements(elements, answers) {
+        let field = element.id.to_string();
+        let value = answers.get(&field).filter(|value| is_answered(value));
+
+        match value {
+            None if element.required => errors.push(FieldError::new(field, "this question is required")),
+            None => {}
+            Some(value) => match check_answer(element.element_type, element.options.as_ref(), value) {
+                Ok(()) => {
+                    cleaned.insert(field, value.clone());
+                }
+                Err(message) => errors.push(FieldError::new(field, message)),
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(JsonValue::Object(cleaned))
+    } else {
+        Err(AppError::FieldErrors(errors))
+    }
+}
+
+/// The elements a respondent was shown: visible given their answers. Pages
+/// are worked out the same way, since skip rules are what move a respondent
+/// past a page; the pages a client says were reached can't excuse one.
+pub fn shown_elements<'a>(elements: &'a [FormElement], answers: &Map<String, JsonValue>) -> Vec<&'a FormElement> {
+    let visible = visible_elements(elements, answers);
+
+    elements
+        .iter()
+        .filter(|element| visible.contains(&element.id))
+        .collect()
+}
+
+pub fn is_answered(value: &JsonValue) -> bool {
+    match value {
+        JsonValue::Null => false,
+        JsonValue::String(s) => !s.trim().is_empty(),
+        JsonValue::Array(items) => !items.is_empty(),
+        _ => true,
+    }
+}
+
+/// Checks a single answer against an element's kind and options.
+pub fn check_answer(
+    element_type: ElementType,
+    options: Option<&JsonValue>,
+    value: &JsonValue,
+) -> Result<(), String> {
+    // Options are validated on write, but rows that predate typed elements may
+    // not parse; those only get the type checks.
+    let options = ElementOptions::parse(element_type, options).ok();
+
+    match element_type {
+        ElementType::ShortText | ElementType::LongText | ElementType::Email => {
+            let text = value.as_str().ok_or("must be a string")?;
+            if element_type == ElementType::Email && !validator::validate_email(text) {
+                return Err("must be a valid email address".to_string());
+            }
+            if let Some(ElementOptions::Text(opts)) = &options {
+                let length = text.chars().count();
+                if let Some(min) = opts.min_length {
+                    if length < min as usize {
+                        return Err(format!("must be at least {} characters", min));
+                    }
+                }
+                if let Some(max) = opts.max_length {
+                    if length > max as usize {
+                        return Err(format!("must be at most {} characters", max));
+                    }
+                }
+            }
+        }
+        ElementType::Number => {
+            let number = value.as_f64().ok_or("must be a number")?;
+            if let Some(ElementOptions::Number(opts)) = &options {
+                if opts.integer_only && number.fract() != 0.0 {
+                    return Err("must be a whole number".to_string());
+                }
+                if let Some(min) = opts.min {
+                    if number < min {
+                        return Err(format!("must be at least {}", min));
+                    }
+                }
+                if let Some(max) = opts.max {
+                    if number > max {
+                        return Err(format!("must be at most {}", max));
+                    }
+                }
+            }
+        }
+        ElementType::SingleChoice | ElementType::Dropdown => {
+            let choice = value.as_str().ok_or("must be a string")?;
+            if let Some(ElementOptions::Choice(opts)) = &options {
+                if !opts.allow_other && !opts.choices.iter().any(|c| c == choice) {
+                    return Err(format!("`{}` is not one of the available choices", choice));
+                }
+            }
+        }
+        ElementType::MultipleChoice => {
+            let items = value.as_array().ok_or("must be an array of choices")?;
+            let mut selected: Vec<&str> = Vec::with_capacity(items.len());
+            for item in items {
+                let choice = item.as_str().ok_or("must be an array of choices")?;
+                if selected.contains(&choice) {
+                    return Err(format!("`{}` was selected more than once", choice));
+                }
+                selected.push(choice);
+            }
+
+            if let Some(ElementOptions::Choice(opts)) = &options {
+                let others = selected
+                    .iter()
+                    .filter(|choice| !opts.choices.iter().any(|c| c == *choice))
+                    .count();
+                if others > 0 && !opts.allow_other {
+                    return Err("contains choices that are not available".to_string());
+                }
+                if others > 1 {
+                    return Err("only one custom answer is allowed".to_string());
+                }
+                if let Some(min) = opts.min_selections {
+                    if selected.len() < min as usize {
+                        return Err(format!("select at least {} choices", min));
+                    }
+                }
+                if let Some(max) = opts.max_selections {
+                    if selected.len() > max as usize {
+                        return Err(format!("select at most {} choices", max));
+                    }
+                }
+            }
+        }
+        ElementType::Date => {
+            let date = value
+                .as_str()
+                .and_then(parse_date)
+                .ok_or("must be a date formatted as YYYY-MM-DD")?;
+            if let Some(ElementOptions::Date(opts)) = &options {
+                if let Some(min) = opts.min_date.as_deref().and_then(parse_date) {
+                    if date < min {
+                        return Err(format!("must not be before {}", min));
+                    }
+                }
+                if let Some(max) = opts.max_date.as_deref().and_then(parse_date) {
+                    if date > max {
+                        return Err(format!("must not be after {}", max));
+                    }
+                }
+            }
+        }
+        ElementType::Time => {
+            let time = value
+                .as_str()
+                .and_then(parse_time)
+                .ok_or("must be a time formatted as HH:MM")?;
+            if let Some(ElementOptions::Time(opts)) = &options {
+                if let Some(min) = opts.min_time.as_deref() {
+                    if parse_time(min).map(|min| time < min).unwrap_or(false) {
+                        return Err(format!("must not be before {}", min));
+                    }
+                }
+                if let Some(max) = opts.max_time.as_deref() {
+                    if parse_time(max).map(|max| time > max).unwrap_or(false) {
+                        return Err(format!("must not be after {}", max));
+                    }
+                }
+            }
+        }
+        ElementType::Rating => {
+            let rating = value.as_i64().ok_or("must be a whole number")?;
+            let max = match &options {
+                Some(ElementOptions::Rating(opts)) => opts.max,
+                _ => 5,
+            };
+            if !(1..=max).contains(&rating) {
+                return Err(format!("must be between 1 and {}", max));
+            }
+        }
+        ElementType::LinearScale => {
+            let point = value.as_i64().ok_or("must be a whole number")?;
+            if let Some(ElementOptions::LinearScale(opts)) = &options {
+                if !(opts.min..=opts.max).contains(&point) {
+                    return Err(format!("must be between {} and {}", opts.min, opts.max));
+                }
+            }
+        }
+        ElementType::FileUpload => {
+            let files = value.as_array().ok_or("must be an array of file ids")?;
+            if files
+                .iter()
+                .any(|file| file.as_str().and_then(|id| Uuid::parse_str(id).ok()).is_none())
+            {
+                return Err("must be an array of file ids".to_string());
+            }
+            if let Some(ElementOptions::FileUpload(opts)) = &options {
+                if files.len() > opts.max_files as usize {
+                    return Err(format!("at most {} files can be attached", opts.max_files));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn fields(result: Result<JsonValue, AppError>) -> Vec<String> {
+        match result {
+            Err(AppError::FieldErrors(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            other => panic!("expected field errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_lengths_count_characters() {
+        let options = json!({ "min_length": 2, "max_length": 3 });
+        let check = |text: &str| check_answer(ElementType::ShortText, Some(&options), &json!(text));
+
+        assert!(check("é").is_err());
+        assert!(check("éé").is_ok());
+        assert!(check("ééé").is_ok());
+        assert!(check("éééé").is_err());
+    }
+
+    #[test]
+    fn emails_must_be_valid() {
+        assert!(check_answer(ElementType::Email, None, &json!("someone@example.com")).is_ok());
+        assert!(check_answer(ElementType::Email, None, &json!("someone")).is_err());
+        assert!(check_answer(ElementType::Email, None, &json!(42)).is_err());
+    }
+
+    #[test]
+    fn numbers_respect_bounds_and_integer_only() {
+        let options = json!({ "min": 1, "max": 10, "integer_only": true });
+        let check = |value: JsonValue| check_answer(ElementType::Number, Some(&options), &value);
+
+        assert!(check(json!(5)).is_ok());
+        assert!(check(json!(5.5)).is_err());
+        assert!(check(json!(0)).is_err());
+        assert!(check(json!(11)).is_err());
+        assert!(check(json!("5")).is_err());
+    }
+
+    #[test]
+    fn single_choice_must_be_listed_unless_other_allowed() {
+        let closed = json!({ "choices": ["Red", "Blue"] });
+        let open = json!({ "choices": ["Red", "Blue"], "allow_other": true });
+
+        assert!(check_answer(ElementType::SingleChoice, Some(&closed), &json!("Red")).is_ok());
+        assert!(check_answer(ElementType::SingleChoice, Some(&closed), &json!("Green")).is_err());
+        assert!(check_answer(ElementType::SingleChoice, Some(&open), &json!("Green")).is_ok());
+    }
+
+    #[test]
+    fn multiple_choice_checks_duplicates_others_and_counts() {
+        let options = json!({
+            "choices": ["A", "B", "C"],
+            "allow_other": true,
+            "min_selections": 2,
+            "max_selections": 3,
+        });
+        let check = |value: JsonValue| check_answer(ElementType::MultipleChoice, Some(&options), &value);
+
+        assert!(check(json!(["A", "B"])).is_ok());
+        assert!(check(json!(["A", "Other"])).is_ok());
+        assert!(check(json!(["A", "A"])).is_err());
+        assert!(check(json!(["A", "X", "Y"])).is_err());
+        assert!(check(json!(["A"])).is_err());
+        assert!(check(json!(["A", "B", "C", "D"])).is_err());
+    }
+
+    #[test]
+    fn dates_and_times_respect_bounds() {
+        let dates = json!({ "min_date": "2024-01-01", "max_date": "2024-12-31" });
+        assert!(check_answer(ElementType::Date, Some(&dates), &json!("2024-06-01")).is_ok());
+        assert!(check_answer(ElementType::Date, Some(&dates), &json!("2023-12-31")).is_err());
+        assert!(check_answer(ElementType::Date, Some(&dates), &json!("01/06/2024")).is_err());
+
+        let times = json!({ "min_time": "09:00", "max_time": "17:00" });
+        assert!(check_answer(ElementType::Time, Some(&times), &json!("12:30")).is_ok());
+        assert!(check_answer(ElementType::Time, Some(&times), &json!("08:59")).is_err());
+    }
+
+    #[test]
+    fn ratings_default_to_five_stars() {
+        assert!(check_answer(ElementType::Rating, None, &json!(5)).is_ok());
+        assert!(check_answer(ElementType::Rating, None, &json!(6)).is_err());
+        assert!(check_answer(ElementType::Rating, None, &json!(0)).is_err());
+        assert!(check_answer(ElementType::Rating, Some(&json!({ "max": 10 })), &json!(10)).is_ok());
+    }
+
+    #[test]
+    fn file_answers_are_upload_ids_up_to_max_files() {
+        let id = Uuid::new_v4().to_string();
+        assert!(check_answer(ElementType::FileUpload, None, &json!([id])).is_ok());
+        assert!(check_answer(ElementType::FileUpload, None, &json!(["not-an-id"])).is_err());
+        assert!(check_answer(ElementType::FileUpload, None, &json!([id, id])).is_err());
+    }
+
+    #[test]
+    fn unparseable_legacy_options_only_get_type_checks() {
+        let legacy = json!(["Red", "Blue"]);
+        assert!(check_answer(ElementType::SingleChoice, Some(&legacy), &json!("Green")).is_ok());
+        assert!(check_answer(ElementType::SingleChoice, Some(&legacy), &json!(1)).is_err());
+    }
+
+    #[test]
+    fn response_must_be_an_object_of_known_questions() {
+        let elements = [FormElement::for_test(ElementType::ShortText, None)];
+
+        assert_eq!(fields(validate_response(&elements, &json!([]))), ["response_data"]);
+        assert_eq!(fields(validate_response(&elements, &json!({ "nope": "x" }))), ["nope"]);
+    }
+
+    #[test]
+    fn required_questions_need_a_real_answer() {
+        let elements = [FormElement {
+            required: true,
+            ..FormElement::for_test(ElementType::ShortText, None)
+        }];
+        let key = elements[0].id.to_string();
+
+        let errors = fields(validate_response(&elements, &json!({ &key: "   " })));
+        assert_eq!(errors, [key.as_str()]);
+
+        let cleaned = validate_response(&elements, &json!({ &key: "hello" })).unwrap();
+        assert_eq!(cleaned, json!({ &key: "hello" }));
+    }
+
+    #[test]
+    fn empty_optional_answers_are_dropped() {
+        let element = FormElement::for_test(ElementType::MultipleChoice, Some(json!({ "choices": ["A"] })));
+        let key = element.id.to_string();
+
+        let cleaned = validate_response(&[element], &json!({ &key: [] })).unwrap();
+        assert_eq!(cleaned, json!({}));
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::{types::Json, PgConnection, PgPool};
+use sqlx::types::JsonValue;
+use uuid::Uuid;
+
+use crate::{
+    db::{fetch_elements, fetch_sections},
+    elements::ElementType,
+    models::{FormElement, FormSection, FormSnapshot, FormVersion},
+};
+
+/// Snapshots the form's current sections and elements as its next version.
+pub async fn create_version(
+    conn: &mut PgConnection,
+    form_id: Uuid,
+    created_by: Uuid,
+) -> Result<FormVersion, sqlx::Error> {
+    // Locking the form serialises version numbers for concurrent publishes
+    let (title, description) = sqlx::query_as::<_, (String, Option<String>)>(
+        "SELECT title, description FROM forms WHERE id = $1 FOR UPDATE"
+    )
+    .bind(form_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let snapshot = FormSnapshot {
+        title,
+        description,
+        sections: fetch_sections(&mut *conn, form_id).await?,
+        elements: fetch_elements(&mut *conn, form_id).await?,
+    };
+
+    sqlx::query_as::<_, FormVersion>(
+        "INSERT INTO form_versions (form_id, version_number, snapshot, created_by)
+         VALUES ($1, COALESCE((SELECT MAX(version_number) FROM form_versions WHERE form_id = $1), 0) + 1, $2, $3)
+         RETURNING *"
+    )
+    .bind(form_id)
+    .bind(Json(&snapshot))
+    .bind(created_by)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+pub async fn latest_version(pool: &PgPool, form_id: Uuid) -> Result<Option<FormVersion>, sqlx::Error> {
+    sqlx::query_as::<_, FormVersion>(
+        "SELECT * FROM form_versions WHERE form_id = $1 ORDER BY version_number DESC LIMIT 1"
+    )
+    .bind(form_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// What respondents are currently served: the latest published version, or
+/// the live sections and elements if the form has never been published.
+pub struct PublishedLayout {
+    pub version_id: Option<Uuid>,
+    pub sections: Vec<FormSection>,
+    pub elements: Vec<FormElement>,
+}
+
+pub async fn published_layout(pool: &PgPool, form_id: Uuid) -> Result<PublishedLayout, sqlx::Error> {
+    match latest_version(pool, form_id).await? {
+        Some(version) => Ok(PublishedLayout {
+            version_id: Some(version.id),
+            sections: version.snapshot.0.sections,
+            elements: version.snapshot.0.elements,
+        }),
+        None => Ok(PublishedLayout {
+            version_id: None,
+            sections: fetch_sections(pool, form_id).await?,
+            elements: fetch_elements(pool, form_id).await?,
+        }),
+    }
+}
+
+/// Replaces the form's live sections and elements with those of `snapshot`,
+/// keeping their ids so stored answers still line up.
+pub async fn restore_snapshot(
+    conn: &mut PgConnection,
+    form_id: Uuid,
+    snapshot: &FormSnapshot,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM form_elements WHERE form_id = $1")
+        .bind(form_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM form_sections WHERE form_id = $1")
+        .bind(form_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for section in &snapshot.sections {
+        sqlx::query(
+            "INSERT INTO form_sections (id, form_id, title, description, order_index)
+             VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(section.id)
+        .bind(form_id)
+        .bind(&section.title)
+        .bind(&section.description)
+        .bind(section.order_index)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for element in &snapshot.elements {
+        sqlx::query(
+            "INSERT INTO form_elements
+             (id, form_id, section_id, element_type, question, required, options, order_index, logic,
+              legacy_element_type, legacy_options, correct_answer, points)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
+        )
+        .bind(element.id)
+        .bind(form_id)
+        .bind(element.section_id)
+        .bind(element.element_type.as_str())
+        .bind(&element.question)
+        .bind(element.required)
+        .bind(&element.options)
+        .bind(element.order_index)
+        .bind(&element.logic)
+        .bind(&element.legacy_element_type)
+        .bind(&element.legacy_options)
+        .bind(&element.correct_answer)
+        .bind(element.points)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query("UPDATE forms SET title = $1, description = $2, updated_at = NOW() WHERE id = $3")
+        .bind(&snapshot.title)
+        .bind(&snapshot.description)
+        .bind(form_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: JsonValue,
+    pub after: JsonValue,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordChange {
+    pub id: Uuid,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RecordDiff {
+    pub added: Vec<JsonValue>,
+    pub removed: Vec<JsonValue>,
+    pub changed: Vec<RecordChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotDiff {
+    pub form: Vec<FieldChange>,
+    pub sections: RecordDiff,
+    pub elements: RecordDiff,
+}
+
+// Bookkeeping columns that change on every save and say nothing about content
+const IGNORED_FIELDS: &[&str] = &["id", "form_id", "created_at", "updated_at"];
+
+pub fn diff_snapshots(before: &FormSnapshot, after: &FormSnapshot) -> SnapshotDiff {
+    let mut form = Vec::new();
+    if before.title != after.title {
+        form.push(FieldChange {
+            field: "title".to_string(),
+            before: before.title.clone().into(),
+            after: after.title.clone().into(),
+        });
+    }
+    if before.description != after.description {
+        form.push(FieldChange {
+            field: "description".to_string(),
+            before: before.description.clone().into(),
+            after: after.description.clone().into(),
+        });
+    }
+
+    SnapshotDiff {
+        form,
+        sections: diff_records(
+            before.sections.iter().map(|s| (s.id, to_json(s))).collect(),
+            after.sections.iter().map(|s| (s.id, to_json(s))).collect(),
+        ),
+        elements: diff_records(
+            before.elements.iter().map(|e| (e.id, to_json(e))).collect(),
+            after.elements.iter().map(|e| (e.id, to_json(e))).collect(),
+        ),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> JsonValue {
+    serde_json::to_value(value).unwrap_or(JsonValue::Null)
+}
+
+fn diff_records(before: Vec<(Uuid, JsonValue)>, after: Vec<(Uuid, JsonValue)>) -> RecordDiff {
+    let mut diff = RecordDiff::default();
+
+    for (id, old) in &before {
+        match after.iter().find(|(other, _)| other == id) {
+            None => diff.removed.push(old.clone()),
+            Some((_, new)) => {
+                let changes = diff_fields(old, new);
+                if !changes.is_empty() {
+                    diff.changed.push(RecordChange { id: *id, changes });
+                }
+            }
+        }
+    }
+
+    for (id, new) in after {
+        if !before.iter().any(|(other, _)| *other == id) {
+            diff.added.push(new);
+        }
+    }
+
+    diff
+}
+
+fn diff_fields(before: &JsonValue, after: &JsonValue) -> Vec<FieldChange> {
+    let (Some(before), Some(after)) = (before.as_object(), after.as_object()) else {
+        return Vec::new();
+    };
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter(|field| !IGNORED_FIELDS.contains(&field.as_str()))
+        .filter_map(|field| {
+            let old = before.get(field).cloned().unwrap_or(JsonValue::Null);
+            let new = after.get(field).cloned().unwrap_or(JsonValue::Null);
+            (old != new).then(|| FieldChange {
+                field: field.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+/// A question as it should be labelled in exports.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportColumn {
+    pub element_id: Uuid,
+    pub label: String,
+    // Type and options from the live form, or the newest version that had it
+    pub element_type: ElementType,
+    pub options: Option<JsonValue>,
+    // The question no longer exists on the live form
+    pub removed: bool,
+    // Earlier wordings, newest first
+    pub previous_labels: Vec<String>,
+}
+
+/// Columns for every question a response could have answered: the live
+/// elements in form order, followed by questions only found in older versions.
+pub async fn export_columns(conn: &mut PgConnection, form_id: Uuid) -> Result<Vec<ExportColumn>, sqlx::Error> {
+    let live = fetch_elements(&mut *conn, form_id).await?;
+    let versions = sqlx::query_as::<_, FormVersion>(
+        "SELECT * FROM form_versions WHERE form_id = $1 ORDER BY version_number DESC"
+    )
+    .bind(form_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut columns: Vec<ExportColumn> = live
+        .iter()
+        .map(|element| ExportColumn {
+            element_id: element.id,
+            label: element.question.clone(),
+            element_type: element.element_type,
+            options: element.options.clone(),
+            removed: false,
+            previous_labels: Vec::new(),
+        })
+        .collect();
+
+    for version in &versions {
+        for element in &version.snapshot.0.elements {
+            match columns.iter_mut().find(|column| column.element_id == element.id) {
+                Some(column) => {
+                    if column.label != element.question && !column.previous_labels.contains(&element.question) {
+                        column.previous_labels.push(element.question.clone());
+                    }
+                }
+                None => columns.push(ExportColumn {
+                    element_id: element.id,
+                    label: element.question.clone(),
+                    element_type: element.element_type,
+                    options: element.options.clone(),
+                    removed: true,
+                    previous_labels: Vec::new(),
+                }),
+            }
+        }
+    }
+
+    // Two questions can end up sharing a label; keep headers unambiguous
+    let mut seen = HashSet::new();
+    for column in &mut columns {
+        if column.removed {
+            column.label = format!("{} (removed)", column.label);
+        }
+        if !seen.insert(column.label.clone()) {
+            column.label = format!("{} [{}]", column.label, column.element_id);
+        }
+    }
+
+    Ok(columns)
+}
@@ -0,0 +1,461 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect::Policy,
+    Client, Url,
+};
+use serde_json::{json, Value as JsonValue};
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::models::Form;
+
+pub const RESPONSE_CREATED: &str = "response.created";
+pub const RESPONSE_DELETED: &str = "response.deleted";
+pub const FORM_CLOSED: &str = "form.closed";
+pub const EVENTS: &[&str] = &[RESPONSE_CREATED, RESPONSE_DELETED, FORM_CLOSED];
+
+// Attempts before a delivery is marked failed, and the wait before the first
+// retry; each later retry waits twice as long as the one before
+const MAX_ATTEMPTS: i32 = 8;
+const RETRY_BASE: Duration = Duration::from_secs(30);
+const RETRY_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a claimed delivery is hidden from other workers; comfortably
+// longer than a request can take
+const CLAIM_LEASE_SECONDS: f64 = 60.0;
+const CLAIM_BATCH: i64 = 20;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// Of the endpoint's reply, kept for the delivery log
+const MAX_LOGGED_BODY: usize = 4096;
+// How often forms that close on their own are looked for
+const CLOSE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const CLOSE_BATCH: i64 = 100;
+
+/// Queues `event` for each of the form's active webhooks that subscribe to
+/// it. Call inside the transaction making the change, so an event is sent
+/// exactly when the change commits.
+pub async fn enqueue(conn: &mut PgConnection, form_id: Uuid, event: &str, data: JsonValue) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO webhook_deliveries (webhook_id, event, payload)
+         SELECT id, $2, $3 FROM webhooks
+         WHERE form_id = $1 AND is_active AND $2 = ANY(events)"
+    )
+    .bind(form_id)
+    .bind(event)
+    .bind(data)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Queues `form.closed` for a form that has stopped taking responses, unless
+/// it was already sent for this closing. Call inside the transaction that
+/// closed it, with the form's row locked.
+pub async fn form_closed(conn: &mut PgConnection, form: &Form) -> Result<(), sqlx::Error> {
+    let first = sqlx::query("UPDATE forms SET close_announced_at = NOW() WHERE id = $1 AND close_announced_at IS NULL")
+        .bind(form.id)
+        .execute(&mut *conn)
+        .await?;
+
+    if first.rows_affected() == 1 {
+        enqueue(conn, form.id, FORM_CLOSED, json!({ "form": form })).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends `form.closed` for published forms whose `closes_at` has passed or
+/// whose response cap is full, for as long as the server runs.
+pub fn spawn_close_watch(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = announce_closes(&pool).await {
+                tracing::error!("announcing closed forms failed: {}", e);
+            }
+            tokio::time::sleep(CLOSE_POLL_INTERVAL).await;
+        }
+    });
+}
+
+async fn announce_closes(pool: &PgPool) -> Result<(), sqlx::Error> {
+    // Forms taking responses again, e.g. after their dates or cap changed
+    sqlx::query(
+        "UPDATE forms SET close_announced_at = NULL
+         WHERE close_announced_at IS NOT NULL AND status = 'published'
+           AND (closes_at IS NULL OR closes_at > NOW())
+           AND (max_responses IS NULL
+                OR max_responses > (SELECT COUNT(*) FROM form_responses r WHERE r.form_id = forms.id))"
+    )
+    .execute(pool)
+    .await?;
+
+    loop {
+        let mut tx = pool.begin().await?;
+
+        let closed = sqlx::query_as::<_, Form>(
+            "SELECT * FROM forms
+             WHERE close_announced_at IS NULL AND status = 'published'
+               AND (closes_at <= NOW()
+                    OR max_responses <= (SELECT COUNT(*) FROM form_responses r WHERE r.form_id = forms.id))
+             LIMIT $1
+             FOR UPDATE SKIP LOCKED"
+        )
+        .bind(CLOSE_BATCH)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for form in &closed {
+            form_closed(&mut tx, form).await?;
+        }
+        tx.commit().await?;
+
+        if (closed.len() as i64) < CLOSE_BATCH {
+            return Ok(());
+        }
+    }
+}
+
+/// Sends queued deliveries in the background for as long as the server runs.
+pub fn spawn_worker(pool: PgPool) -> anyhow::Result<()> {
+    // Redirects could bounce a signed payload somewhere the owner never chose,
+    // and a proxy would resolve hosts where we can't check them
+    let client = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(Policy::none())
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicResolver))
+        .user_agent("reforms-webhooks")
+        .build()?;
+
+    tokio::spawn(async move {
+        loop {
+            match deliver_due(&pool, &client).await {
+                // A full batch suggests there's more waiting
+                Ok(sent) if sent as i64 == CLAIM_BATCH => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("webhook delivery failed: {}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct DueDelivery {
+    id: Uuid,
+    event: String,
+    payload: JsonValue,
+    attempts: i32,
+    created_at: OffsetDateTime,
+    url: String,
+    secret: String,
+}
+
+struct Outcome {
+    status: Option<u16>,
+    body: Option<String>,
+    error: Option<String>,
+}
+
+async fn deliver_due(pool: &PgPool, client: &Client) -> Result<usize, sqlx::Error> {
+    // SKIP LOCKED and the lease let several servers share the queue
+    let due = sqlx::query_as::<_, DueDelivery>(
+        "WITH due AS (
+             SELECT d.id FROM webhook_deliveries d
+             JOIN webhooks w ON w.id = d.webhook_id
+             WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.is_active
+             ORDER BY d.next_attempt_at
+             LIMIT $1
+             FOR UPDATE OF d SKIP LOCKED
+         )
+         UPDATE webhook_deliveries d
+         SET next_attempt_at = NOW() + make_interval(secs => $2)
+         FROM due, webhooks w
+         WHERE d.id = due.id AND w.id = d.webhook_id
+         RETURNING d.id, d.event, d.payload, d.attempts, d.created_at, w.url, w.secret"
+    )
+    .bind(CLAIM_BATCH)
+    .bind(CLAIM_LEASE_SECONDS)
+    .fetch_all(pool)
+    .await?;
+
+    let sent = due.len();
+    let outcomes = futures::future::join_all(due.iter().map(|delivery| send(client, delivery))).await;
+
+    for (delivery, outcome) in due.iter().zip(outcomes) {
+        let attempts = delivery.attempts + 1;
+        let succeeded = outcome.status.is_some_and(|status| (200..300).contains(&status));
+        let status = if succeeded {
+            "succeeded"
+        } else if attempts >= MAX_ATTEMPTS {
+            "failed"
+        } else {
+            "pending"
+        };
+
+        sqlx::query(
+            "UPDATE webhook_deliveries
+             SET status = $2, attempts = $3, next_attempt_at = NOW() + make_interval(secs => $4),
+                 response_status = $5, response_body = $6, error = $7,
+                 delivered_at = CASE WHEN $2 = 'succeeded' THEN NOW() END
+             WHERE id = $1"
+        )
+        .bind(delivery.id)
+        .bind(status)
+        .bind(attempts)
+        .bind(retry_delay(attempts).as_secs_f64())
+        .bind(outcome.status.map(i32::from))
+        .bind(outcome.body)
+        .bind(outcome.error)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(sent)
+}
+
+fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.clamp(1, 16) as u32 - 1;
+    RETRY_BASE.saturating_mul(1 << doublings).min(RETRY_MAX)
+}
+
+async fn send(client: &Client, delivery: &DueDelivery) -> Outcome {
+    let body = json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "created_at": delivery.created_at,
+        "data": delivery.payload,
+    })
+    .to_string();
+    let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+    // Hosts are checked as they're resolved, but addresses aren't resolved
+    if let Some(error) = Url::parse(&delivery.url)
+        .ok()
+        .and_then(|url| url.host_str()?.trim_matches(['[', ']']).parse::<IpAddr>().ok())
+        .filter(|ip| !is_public(*ip))
+        .map(|ip| format!("refusing to deliver to private address {}", ip))
+    {
+        return Outcome {
+            status: None,
+            body: None,
+            error: Some(error),
+        };
+    }
+
+    let request = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Reforms-Event", &delivery.event)
+        .header("X-Reforms-Delivery", delivery.id.to_string())
+        .header("X-Reforms-Signature", signature(&delivery.secret, timestamp, &body))
+        .body(body);
+
+    match request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            Outcome {
+                status: Some(status),
+                body: Some(truncate(body, MAX_LOGGED_BODY)),
+                error: None,
+            }
+        }
+        Err(e) => Outcome {
+            status: None,
+            body: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Checks that `url`'s host is, or only resolves to, public addresses, so a
+/// webhook can't be pointed at the server's own network.
+pub async fn check_destination(url: &Url) -> Result<(), String> {
+    let host = url.host_str().ok_or("must have a host")?;
+    let port = url.port_or_known_default().unwrap_or(0);
+    let addrs: Vec<SocketAddr> = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| format!("could not resolve {}", host))?
+            .collect(),
+    };
+
+    match addrs.iter().find(|addr| !is_public(addr.ip())) {
+        Some(addr) => Err(format!("must not point at a private or local address ({})", addr.ip())),
+        None => Ok(()),
+    }
+}
+
+// Resolves hosts as usual but refuses any that lead to a private or local
+// address, which also covers DNS records changed after the webhook was saved
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(format!("refusing to deliver to private address {} for {}", addr.ip(), name).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && b & 0xfe == 18)
+                // Reserved, 240.0.0.0/4
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7, and link-local, fe80::/10
+                    || segments[0] & 0xfe00 == 0xfc00
+                    || segments[0] & 0xffc0 == 0xfe80
+                    // IPv4-compatible, ::a.b.c.d
+                    || segments[..6] == [0; 6]
+                    // NAT64, 64:ff9b::/96 and the local-use 64:ff9b:1::/48,
+                    // which a gateway may translate to any IPv4 address
+                    || segments[..2] == [0x64, 0xff9b])
+            }
+        },
+    }
+}
+
+/// `t=<unix time>,v1=<hex HMAC-SHA256 of "<unix time>.<body>">`. Receivers
+/// should recompute it with their secret and reject stale timestamps, which
+/// stops old payloads from being replayed.
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+fn truncate(mut text: String, max_bytes: usize) -> String {
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn signature_is_hmac_of_timestamp_and_body() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(b"1700000000.{}");
+        let expected = format!("t=1700000000,v1={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert_eq!(signature("secret", 1_700_000_000, "{}"), expected);
+        assert_ne!(signature("other", 1_700_000_000, "{}"), expected);
+        assert_ne!(signature("secret", 1_700_000_001, "{}"), expected);
+    }
+
+    #[test]
+    fn private_and_local_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+            "192.0.2.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "250.1.2.3",
+            "64:ff9b::7f00:1",
+            "64:ff9b::808:808",
+            "64:ff9b:1::1",
+            "::127.0.0.1",
+            "::8.8.8.8",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn ordinary_addresses_are_public() {
+        for ip in [
+            "8.8.8.8",
+            "1.1.1.1",
+            "100.128.0.1",
+            "198.20.0.1",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn retries_back_off_up_to_a_cap() {
+        assert_eq!(retry_delay(0), RETRY_BASE);
+        assert_eq!(retry_delay(1), RETRY_BASE);
+        assert_eq!(retry_delay(2), RETRY_BASE * 2);
+        assert_eq!(retry_delay(3), RETRY_BASE * 4);
+        assert_eq!(retry_delay(100), RETRY_MAX);
+    }
+
+    #[test]
+    fn truncate_respects_char_boundaries() {
+        assert_eq!(truncate("héllo".to_string(), 2), "h");
+        assert_eq!(truncate("hello".to_string(), 10), "hello");
+    }
+}